@@ -1,6 +1,11 @@
 //! Handles input and output for the terminal interface
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use rusqlite::types::Value;
+use serde::{ser::SerializeMap, Serialize, Serializer};
+
+use corkboard::{db::{ItemFilter, ItemRef, JournalEntry, QueryRows, Setting}, rss::Item, text::terminal_safe};
 
 ///The actions available to the user of the program.
 pub enum Operation {
@@ -11,7 +16,7 @@ pub enum Operation {
 	///List all the feeds in the database
 	Feeds,
 	///Show which feeds are new
-	New,
+	New(OutputFormat),
 	///Mark an item as read
 	Mark(Vec<usize>),
 	///Mark an item as read, using it's hash
@@ -20,21 +25,88 @@ pub enum Operation {
 	MarkAll,
 	///Remove a feed from the db
 	Remove(String),
+	///Find and repair inconsistencies in the db, only report them if true
+	Doctor(bool),
+	///Parse a feed (url or file) and report problems, does not use the db
+	Validate(String),
+	///Parse a feed (url, file or stdin) and print it as JSON, does not use the db
+	Parse(String),
+	///Full text search over all the stored items
+	Search(String, ItemFilter, OutputFormat),
+	///List the items read recently, most recent first
+	History(ItemFilter, OutputFormat),
+	///Delete old read items, following the retention settings
+	Prune,
+	///List all the settings
+	ShowConfig,
+	///Change a setting, globally or for one feed (None as the value unsets it)
+	SetConfig(Setting, Option<i64>, Option<String>),
+	///Run a read only SQL query, meant for the views
+	Sql(String, OutputFormat),
+	///Copy the database to a file
+	Backup(String),
+	///Replace the database with a backup
+	Restore(String),
+	///Encrypt a plaintext database, with the key from the environment
+	Encrypt,
+	///Change the key of an encrypted database, to the new one from the environment
+	Rekey,
+	///Mark items as unread, given by position in the history, hash or link
+	Unmark(Vec<ItemRef>),
+	///Star items, given by quickmark, hash or link
+	Star(Vec<ItemRef>),
+	///Unstar items, given by quickmark, hash or link
+	Unstar(Vec<ItemRef>),
+	///List the starred items
+	Starred(OutputFormat),
+	///Snooze items, given by quickmark, hash or link, until the date
+	Snooze(Vec<ItemRef>, DateTime<Utc>),
+	///List the snoozed items
+	Snoozed(OutputFormat),
+	///Revert this many of the last operations
+	Undo(usize),
+	///List the operations that can be undone
+	UndoList(OutputFormat),
 	///Print the help message for the program
 	Help
 }
 
+///How lists of items are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+	///One line per item, meant for people
+	#[default]
+	Text,
+	///A JSON array of items, meant for scripts
+	Json
+}
+
 #[derive(Debug)]
 ///Errors ocurring while parsing arguments
 pub enum ParseErr {
 	NoArguments,
-	NotACommand
+	NotACommand,
+	///An option that the command doesn't take
+	UnknownOption(String),
+	///An option that needs a value was the last argument
+	MissingValue(String),
+	///The value given to an option can't be used (option, value)
+	InvalidValue(String, String),
+	///A date that has to be in the future isn't (option, value)
+	NotInTheFuture(String, String),
+	///Two arguments that can't be used together
+	Conflicting(String, String)
 }
 impl std::fmt::Display for ParseErr {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		match self {
 			ParseErr::NoArguments => write!(f, "No arguments passed to the program"),
-			ParseErr::NotACommand => write!(f, "Not a valid command")
+			ParseErr::NotACommand => write!(f, "Not a valid command"),
+			ParseErr::UnknownOption(option) => write!(f, "Unknown option {option}"),
+			ParseErr::MissingValue(option) => write!(f, "Option {option} needs a value"),
+			ParseErr::InvalidValue(option, value) => write!(f, "\"{value}\" is not a valid value for {option}"),
+			ParseErr::NotInTheFuture(option, value) => write!(f, "\"{value}\" is not in the future, {option} needs a later time"),
+			ParseErr::Conflicting(first, second) => write!(f, "Can't use {first} and {second} together")
 		}
 	}
 }
@@ -47,11 +119,11 @@ pub fn parse_arguments(string_args:Vec<String>) -> Result<Operation> {
 	match string_args[1].as_str() {
 		"add" if string_args.len() >= 3 => Ok(Operation::Add(string_args[2].clone())),
 
-		"up" => Ok(Operation::Up),
+		"up" => no_arguments(&string_args[2..]).map(|()| Operation::Up),
 
 		"feeds" => Ok(Operation::Feeds),
 
-		"new" => Ok(Operation::New),
+		"new" => Ok(Operation::New(parse_format_only(&string_args[2..])?)),
 
 		"mark" if string_args.len() == 3 && string_args[2] == "--all" => {
 			Ok(Operation::MarkAll)
@@ -71,8 +143,399 @@ pub fn parse_arguments(string_args:Vec<String>) -> Result<Operation> {
 
 		"remove" if string_args.len() >= 3 => Ok(Operation::Remove(string_args[2].clone())),
 
+		"doctor" if string_args.len() == 3 && string_args[2] == "--dry-run" => Ok(Operation::Doctor(true)),
+
+		"doctor" => no_arguments(&string_args[2..]).map(|()| Operation::Doctor(false)),
+
+		"validate" if string_args.len() >= 3 => Ok(Operation::Validate(string_args[2].clone())),
+
+		"parse" if string_args.len() >= 3 => Ok(Operation::Parse(string_args[2].clone())),
+
+		"search" if string_args.len() >= 3 => parse_search(&string_args[2..]),
+
+		"history" => parse_history(&string_args[2..]),
+
+		"prune" => no_arguments(&string_args[2..]).map(|()| Operation::Prune),
+
+		"config" if string_args.len() == 2 => Ok(Operation::ShowConfig),
+
+		"config" => parse_config(&string_args[2..]),
+
+		"sql" if string_args.len() >= 3 => parse_sql(&string_args[2..]),
+
+		"backup" if string_args.len() == 3 => Ok(Operation::Backup(string_args[2].clone())),
+
+		"restore" if string_args.len() == 3 => Ok(Operation::Restore(string_args[2].clone())),
+
+		"encrypt" => no_arguments(&string_args[2..]).map(|()| Operation::Encrypt),
+
+		"rekey" => no_arguments(&string_args[2..]).map(|()| Operation::Rekey),
+
+		"unmark" if string_args.len() >= 3 => Ok(Operation::Unmark(history_refs(&string_args[2..]))),
+
+		"star" if string_args.len() >= 3 => Ok(Operation::Star(item_refs(&string_args[2..]))),
+
+		"unstar" if string_args.len() >= 3 => Ok(Operation::Unstar(item_refs(&string_args[2..]))),
+
+		"starred" => Ok(Operation::Starred(parse_format_only(&string_args[2..])?)),
+
+		"snooze" if string_args.len() >= 4 => {
+			let (until, items) = string_args[2..].split_last().expect("at least two arguments");
+			Ok(Operation::Snooze(item_refs(items), parse_snooze_end(until)?))
+		},
+
+		"snoozed" => Ok(Operation::Snoozed(parse_format_only(&string_args[2..])?)),
+
+		"undo" if string_args.len() >= 3 && string_args[2] == "--list" => {
+			Ok(Operation::UndoList(parse_format_only(&string_args[3..])?))
+		},
+
+		"undo" if string_args.len() == 3 => match string_args[2].parse() {
+			Ok(count) if count > 0 => Ok(Operation::Undo(count)),
+			_ => Err(ParseErr::InvalidValue(String::from("undo"), string_args[2].clone()).into())
+		},
+
+		"undo" if string_args.len() == 2 => Ok(Operation::Undo(1)),
+
 		"help" | "-h" | "--help" => Ok(Operation::Help),
 
 		_ => Err(ParseErr::NotACommand.into())
 	}
 }
+
+///Arguments of the commands that take none, any given is an error
+fn no_arguments(args: &[String]) -> Result<()> {
+	match args.first() {
+		Some(option) => Err(ParseErr::UnknownOption(option.clone()).into()),
+		None => Ok(())
+	}
+}
+
+///Arguments of the commands whose only option is --format
+fn parse_format_only(args: &[String]) -> Result<OutputFormat> {
+	let mut format = OutputFormat::default();
+	let mut options = args.iter();
+	while let Some(option) = options.next() {
+		match option.as_str() {
+			"--format" => format = parse_format(option, options.next())?,
+			_ => return Err(ParseErr::UnknownOption(option.clone()).into())
+		}
+	}
+	Ok(format)
+}
+
+///Items given by quickmark, hash or link
+fn item_refs(args: &[String]) -> Vec<ItemRef> {
+	args.iter().map(|item| ItemRef::from(item.as_str())).collect()
+}
+
+///Items given by position in the history, hash or link
+fn history_refs(args: &[String]) -> Vec<ItemRef> {
+	args.iter()
+		.map(|item| match item.parse() {
+			Ok(position) => ItemRef::HistoryPosition(position),
+			Err(_) => ItemRef::from(item.as_str())
+		})
+		.collect()
+}
+
+///Arguments of search: the query (may be anywhere) and the filtering options.
+fn parse_search(args: &[String]) -> Result<Operation> {
+	let mut query = None;
+	let mut filter = ItemFilter::default();
+	let mut format = OutputFormat::default();
+
+	let mut options = args.iter();
+	while let Some(option) = options.next() {
+		if parse_listing_option(option, &mut options, &mut filter, &mut format)? {
+			continue;
+		}
+		match option.as_str() {
+			"--read" => filter.read = Some(true),
+			"--unread" => filter.read = Some(false),
+			_ if option.starts_with("--") => return Err(ParseErr::UnknownOption(option.clone()).into()),
+			_ => query = Some(option.clone())
+		}
+	}
+
+	let query = query.ok_or(ParseErr::MissingValue(String::from("search")))?;
+	Ok(Operation::Search(query, filter, format))
+}
+
+///Arguments of history, only options.
+fn parse_history(args: &[String]) -> Result<Operation> {
+	let mut filter = ItemFilter::default();
+	let mut format = OutputFormat::default();
+
+	let mut options = args.iter();
+	while let Some(option) = options.next() {
+		if !parse_listing_option(option, &mut options, &mut filter, &mut format)? {
+			return Err(ParseErr::UnknownOption(option.clone()).into());
+		}
+	}
+	Ok(Operation::History(filter, format))
+}
+
+///Options shared by the commands that list items (--feed, --since, --until and --format),
+///the ones taking a value consume it from _rest_.
+///Returns false if _option_ is not one of them.
+fn parse_listing_option(
+	option: &str,
+	rest: &mut std::slice::Iter<String>,
+	filter: &mut ItemFilter,
+	format: &mut OutputFormat
+) -> Result<bool> {
+	match option {
+		"--feed" => filter.feed = Some(rest.next()
+			.ok_or(ParseErr::MissingValue(option.to_string()))?
+			.clone()),
+		"--since" => filter.since = Some(parse_date(option, rest.next(), false)?),
+		"--until" => filter.until = Some(parse_date(option, rest.next(), true)?),
+		"--format" => *format = parse_format(option, rest.next())?,
+		_ => return Ok(false)
+	}
+	Ok(true)
+}
+
+///Arguments of config: a setting, then a value or --unset, maybe with --feed.
+fn parse_config(args: &[String]) -> Result<Operation> {
+	let setting: Setting = args[0].parse()?;
+	let mut value = None;
+	let mut unset = false;
+	let mut feed = None;
+
+	let mut options = args[1..].iter();
+	while let Some(option) = options.next() {
+		match option.as_str() {
+			"--unset" => unset = true,
+			"--feed" => feed = Some(options.next()
+				.ok_or(ParseErr::MissingValue(option.clone()))?
+				.clone()),
+			_ if option.starts_with("--") => return Err(ParseErr::UnknownOption(option.clone()).into()),
+			_ => value = Some(setting.parse_value(option)?)
+		}
+	}
+
+	match (value, unset) {
+		(None, false) => Err(ParseErr::MissingValue(setting.name().to_string()).into()),
+		(Some(_), true) => Err(ParseErr::Conflicting(String::from("a value"), String::from("--unset")).into()),
+		(value, _) => Ok(Operation::SetConfig(setting, value, feed))
+	}
+}
+
+///Arguments of sql: the query (may be anywhere) and --format.
+fn parse_sql(args: &[String]) -> Result<Operation> {
+	let mut query = None;
+	let mut format = OutputFormat::default();
+
+	let mut options = args.iter();
+	while let Some(option) = options.next() {
+		match option.as_str() {
+			"--format" => format = parse_format(option, options.next())?,
+			_ if option.starts_with("--") => return Err(ParseErr::UnknownOption(option.clone()).into()),
+			_ => query = Some(option.clone())
+		}
+	}
+
+	let query = query.ok_or(ParseErr::MissingValue(String::from("sql")))?;
+	Ok(Operation::Sql(query, format))
+}
+
+///Value of a --format option
+fn parse_format(option: &str, value: Option<&String>) -> Result<OutputFormat> {
+	let value = value.ok_or(ParseErr::MissingValue(option.to_string()))?;
+	match value.as_str() {
+		"text" => Ok(OutputFormat::Text),
+		"json" => Ok(OutputFormat::Json),
+		_ => Err(ParseErr::InvalidValue(option.to_string(), value.clone()).into())
+	}
+}
+
+///Value of a date option, either a day (YYYY-MM-DD, in UTC) or a full RFC 3339 date.
+///
+///A day used as the end of a range includes the whole day.
+fn parse_date(option: &str, value: Option<&String>, end_of_day: bool) -> Result<DateTime<Utc>> {
+	let value = value.ok_or(ParseErr::MissingValue(option.to_string()))?;
+	let invalid = || ParseErr::InvalidValue(option.to_string(), value.clone());
+
+	if let Ok(day) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+		let (hour, minute, second) = if end_of_day { (23, 59, 59) } else { (0, 0, 0) };
+		let time = NaiveTime::from_hms_opt(hour, minute, second).ok_or_else(invalid)?;
+		return Ok(Utc.from_utc_datetime(&day.and_time(time)));
+	}
+	DateTime::parse_from_rfc3339(value)
+		.map(|date| date.with_timezone(&Utc))
+		.map_err(|_| invalid().into())
+}
+
+///When a snooze ends: either a time from now in hours, days or weeks (say 12h, 3d or 2w)
+///or a date, like the ones of --since. It has to be in the future, the item would be back right away.
+fn parse_snooze_end(value: &String) -> Result<DateTime<Utc>> {
+	let now = Utc::now();
+	let relative = [("h", 1), ("d", 24), ("w", 24 * 7)].into_iter()
+		.find_map(|(suffix, hours)| {
+			let amount = value.strip_suffix(suffix)?.parse::<u32>().ok()?;
+			Some(now.checked_add_signed(Duration::hours(i64::from(amount) * hours)))
+		});
+	let end = match relative {
+		Some(end) => end.ok_or_else(|| ParseErr::InvalidValue(String::from("snooze"), value.clone()))?,
+		None => parse_date("snooze", Some(value), false)?
+	};
+
+	if end <= now {
+		return Err(ParseErr::NotInTheFuture(String::from("snooze"), value.clone()).into());
+	}
+	Ok(end)
+}
+
+///An item as printed in JSON, with its quickmark (if it has one)
+#[derive(Serialize)]
+struct ListedItem<'a> {
+	quickmark: Option<i32>,
+	#[serde(flatten)]
+	item: &'a Item
+}
+
+///Print the reading history, as text each line starts with when the item was read (local time).
+pub fn print_history(items: &[Item], format: OutputFormat) -> Result<()> {
+	print_dated_items(items, |item| item.read_at, format)
+}
+
+///Print the snoozed items, as text each line starts with when the item comes back (local time).
+pub fn print_snoozed(items: &[Item], format: OutputFormat) -> Result<()> {
+	print_dated_items(items, |item| item.snoozed_until, format)
+}
+
+///Print items without quickmarks, as text each line starts with the date picked by _date_ (in local time).
+fn print_dated_items(items: &[Item], date: fn(&Item) -> Option<DateTime<Utc>>, format: OutputFormat) -> Result<()> {
+	match format {
+		OutputFormat::Text => {
+			for item in items {
+				let date = date(item)
+					.map(|date| date.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+					.unwrap_or_default();
+				println!("{} {}", date, item_line(item, None));
+			}
+			Ok(())
+		},
+		OutputFormat::Json => {
+			let items: Vec<(Item, Option<i32>)> = items.iter().map(|item| (item.clone(), None)).collect();
+			print_items(&items, format)
+		}
+	}
+}
+
+///Print the operations that can be undone, as text each line starts with how many
+///undos reach it and when it was done (local time).
+pub fn print_journal(entries: &[JournalEntry], format: OutputFormat) -> Result<()> {
+	match format {
+		OutputFormat::Text => {
+			for (position, entry) in entries.iter().enumerate() {
+				println!("{} {} {} ({} changes)",
+						 position + 1,
+						 entry.done_at.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+						 terminal_safe(&entry.command),
+						 entry.changes);
+			}
+		},
+		OutputFormat::Json => {
+			let json = serde_json::to_string_pretty(entries)
+				.context("Could not turn the journal into JSON")?;
+			println!("{json}");
+		}
+	}
+	Ok(())
+}
+
+///A single item as a line of text, starting with its quickmark or (without one) its hash.
+fn item_line(item: &Item, quickmark: Option<i32>) -> String {
+	let mark = match quickmark {
+		Some(position) => position.to_string(),
+		None => item.title_or_description_hash.clone()
+	};
+	format!("{} -> [{}] {}",
+			mark,
+			item.display_link().unwrap_or(String::from("No link")),
+			item.display_title())
+}
+
+///Print a list of items in the format asked for.
+///
+///As text every item gets a line, starting with its quickmark or,
+///if it has none (say it was already read), with its hash.
+pub fn print_items(items: &[(Item, Option<i32>)], format: OutputFormat) -> Result<()> {
+	match format {
+		OutputFormat::Text => {
+			for (item, quickmark) in items {
+				println!("{}", item_line(item, *quickmark));
+			}
+		},
+		OutputFormat::Json => {
+			let listed: Vec<ListedItem> = items.iter()
+				.map(|(item, quickmark)| ListedItem { quickmark: *quickmark, item })
+				.collect();
+			let json = serde_json::to_string_pretty(&listed)
+				.context("Could not turn the items into JSON")?;
+			println!("{json}");
+		}
+	}
+	Ok(())
+}
+
+///A row printed as a JSON object, the keys in the order of the columns
+struct JsonRow<'a> {
+	columns: &'a [String],
+	values: &'a [Value]
+}
+impl Serialize for JsonRow<'_> {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let mut map = serializer.serialize_map(Some(self.columns.len()))?;
+		for (column, value) in self.columns.iter().zip(self.values) {
+			match value {
+				Value::Null => map.serialize_entry(column, &())?,
+				Value::Integer(i) => map.serialize_entry(column, i)?,
+				Value::Real(r) => map.serialize_entry(column, r)?,
+				Value::Text(t) => map.serialize_entry(column, t)?,
+				Value::Blob(b) => map.serialize_entry(column, b)?
+			}
+		}
+		map.end()
+	}
+}
+
+///A single value as text, NULL is left empty
+fn value_text(value: &Value) -> String {
+	match value {
+		Value::Null => String::new(),
+		Value::Integer(i) => i.to_string(),
+		Value::Real(r) => r.to_string(),
+		Value::Text(t) => terminal_safe(t),
+		Value::Blob(b) => format!("<{} bytes>", b.len())
+	}
+}
+
+///Print the result of a query.
+///
+///As text the first line has the column names and then every row gets a line,
+///all separated by tabs. As JSON it's an array with an object per row.
+pub fn print_rows(rows: &QueryRows, format: OutputFormat) -> Result<()> {
+	match format {
+		OutputFormat::Text => {
+			let header: Vec<String> = rows.columns.iter().map(|c| terminal_safe(c)).collect();
+			println!("{}", header.join("\t"));
+			for row in &rows.rows {
+				let values: Vec<String> = row.iter().map(value_text).collect();
+				println!("{}", values.join("\t"));
+			}
+		},
+		OutputFormat::Json => {
+			let objects: Vec<JsonRow> = rows.rows.iter()
+				.map(|values| JsonRow { columns: &rows.columns, values })
+				.collect();
+			let json = serde_json::to_string_pretty(&objects)
+				.context("Could not turn the rows into JSON")?;
+			println!("{json}");
+		}
+	}
+	Ok(())
+}
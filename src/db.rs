@@ -1,10 +1,14 @@
 //! Interface into the corkboard sqlite database
 //!
-//! We have three tables: _channels_, _items_ and _quickmarks_
+//! The main tables are _channels_, _items_ and _quickmarks_
 //!
 //! _channels_ stores the RSS feeds and owns many (or zero) _items_.
 //!
 //! _items_ store specific entries from a feed, are owned by _channels_.
+//! An item is identified by its hash within its channel, the same post may be in
+//! several feeds (say a blog's main feed and one of its category feeds) and so in
+//! several rows. Those rows are the same item to the user: they share one quickmark
+//! and marking one as read marks all of them.
 //!
 //! _quickmarks_ holds the mark system (that simplifies the usage of **corkboard new**)
 //!
@@ -33,60 +37,1707 @@
 //!
 //! Quickmark rules:
 //! 1. If __new__ is run then all marks are reset (using publishing order).
-//! 	(Only unmarked items are considered).
-//! 2. __add__ and __up__ add quickmarks at the end, don't change older ones.
+//!    (Only unmarked items are considered).
+//! 2. __add__ and __up__ add quickmarks at the end, don't change older ones
+//!    (but __up__ deletes the ones of the items it expires, see Retention).
 //! 3. __mark__ & __mark all__ delete the quickmark associated with it.
 //! 4. __remove__ deletes all the quickmarks associated with it, does not affect the rest.
-//! 5. All other commands don't alter the quickmarks.
+//! 5. __star__ and __snooze__ delete the quickmark of the item, __unstar__ and __unmark__ give an unread item one at the end.
+//! 6. __undo__ deletes the quickmarks of items no longer unread, and gives the ones back in the queue one at the end.
+//! 7. All other commands don't alter the quickmarks.
+//!
+//! A position holds a single item and an item has a single position (the table's constraints
+//! make sure of it), marks are given by single statements numbering up from the last one.
+//! Marking a position that no item holds is an error.
+//!
+//! Starred items are kept apart from the unread ones: they have no quickmark,
+//! __new__ doesn't list them and __mark --all__ leaves them alone. Like read,
+//! starred belongs to the item, so it's the same in every feed that has it.
+//!
+//! Snoozed items are out of the queue in the same way until their _snoozed_until_ comes,
+//! then they are unread items like the others, except that __new__ gives them the first
+//! quickmarks. The date stays until the item is read, that's how we know it was snoozed.
+//!
+//! Schema versions:
+//!
+//! The schema is built by applying [MIGRATIONS] in order, the number of migrations
+//! applied to a database is stored in it (as `PRAGMA user_version`).
+//! On startup we apply the ones missing, each in its own transaction,
+//! after taking a snapshot of the database (see Backups).
+//! A database with a version higher than the migrations we know of was written
+//! by a newer corkboard, and we refuse to touch it.
+//!
+//! Retention:
+//!
+//! Read items can be pruned, following the settings (see [Setting]).
+//! A pruned item leaves a row in _tombstones_ with its channel and hash, the feed may
+//! still list it and it must not come back as unread on the next __up__.
+//!
+//! Unread items can expire too: __up__ marks as read the ones that have waited in the queue
+//! longer than expire_unread_days, with _auto_read_ set so that they can be told apart
+//! from the ones the user read (marking or unmarking an item by hand clears it).
+//! The wait counts from _queued_at_, when the item last came back to the queue (unmarked,
+//! unstarred or at the end of its snooze), or else from when it was first seen.
+//!
+//! Full text search:
+//!
+//! _items_search_ is an FTS5 index over the title, description and content of every item.
+//! It doesn't hold the text itself but points to _items_ by id, triggers on _items_
+//! keep it up to date so no code outside the schema needs to think about it.
+//!
+//! Dates:
+//!
+//! Dates are stored as unix timestamps (seconds, UTC), so they sort and compare in SQL.
+//! Items are ordered by publishing date, an item without one is placed by when we first saw it.
+//! Items with neither (seen before corkboard kept track of that) go after all the others,
+//! in the order they were added.
+//!
+//! Views:
+//!
+//! The tables are ours to change, people querying the database (say with __sql__)
+//! should use the views instead, they are a promise:
+//! - _v1_unread_items_: every unread item with its quickmark (if it has one) and its feed.
+//! - _v1_feed_stats_: every feed with how many items it has, how many are read, and its latest dates.
+//! - _v1_history_: every read item with when it was read (NULL if read before we kept track) and its feed.
+//! - _v1_starred_items_: every starred item with when it was starred, whether it's read, and its feed.
+//! - _v1_snoozed_items_: every item still snoozed with when it comes back, and its feed.
+//!
+//! An item in several feeds is in several rows, one per feed. Dates are ISO 8601 text in UTC.
+//! A migration that changes the tables under a view must recreate it with the same columns
+//! and meaning. A view that needs to change becomes a new `v2_` one, the old one stays.
+//!
+//! Backups:
+//!
+//! Copies of the database are made with SQLite's online backup API, which reads it
+//! as one consistent transaction, so it's safe while other connections write.
+//! Before migrating and before the commands that delete or mark many items at once
+//! (__remove__, __mark --all__, __prune__ and __restore__ itself) we take a snapshot into
+//! `<database>.snapshots/`, keeping the newest [SNAPSHOTS_KEPT].
+//! A restore checks that the file is a corkboard database we can use, then copies it
+//! over the current one (again with the backup API, other connections see the change).
+//!
+//! Journal:
+//!
+//! Commands that change the database run through [Database::journaled], which adds an entry
+//! to _journal_ and, until it's done, triggers record every insert, update and delete in
+//! channels, items, tombstones and settings into _journal_changes_: the key of a new row,
+//! or the old row (as JSON) for the others. __undo__ goes through the changes backwards.
+//! Quickmarks aren't journaled, they are rebuilt after undoing (see rule 6).
+//! Only the newest operations can be undone, in order, and entries older than
+//! keep_journal_days are forgotten. __doctor__ isn't journaled, undoing a repair would bring the problem back.
+//! Neither is __up__, which often runs from a timer: an __undo__ after it reverts what the user did last.
+//! So that the rows undo brings back can't clash with the items __up__ adds meanwhile, item ids
+//! come from AUTOINCREMENT and are never reused (see migration 10).
+//! A migration adding columns that commands change must recreate the journal triggers (and [UNDO_CHANGES]).
+//!
+//! Encryption:
+//!
+//! Built with the `encryption` feature corkboard uses SQLCipher, and a database opened
+//! with a [DatabaseKey] is encrypted with it, along with its snapshots and backups.
+//! SQLCipher can't encrypt a database in place, [Database::change_key] exports it to
+//! a new file under the new key and moves that over the old one. The snapshots are exported
+//! the same way, the ones it can't open (under an older key) are deleted.
+//!
+//! Concurrency:
+//!
+//! Several corkboards may use the database at once (say an __up__ from a timer while the
+//! user runs __new__). The database is in WAL mode, readers never wait for the writer,
+//! and writers wait for each other (up to a timeout) instead of failing.
+//! Updates also take an advisory lock, two at once would fetch every feed twice.
+//! Every connection holds a shared lock on `<database>.in-use` too, changing the key
+//! replaces the file under them so it needs that lock for itself.
+//!
+//! Foreign keys:
+//!
+//! SQLite only enforces foreign keys (and so the ON DELETE CASCADE clauses) on connections
+//! that ask for it, we do on every connection right after migrating.
+//! Databases used before that may still hold orphaned rows, __doctor__ finds and removes them.
 
 use anyhow::{anyhow, Context, Result};
-use rusqlite::{Connection, params};
+use chrono::{DateTime, TimeZone, Utc};
+use fs4::fs_std::FileExt;
+use rusqlite::{
+	backup::{Backup, StepResult},
+	Connection,
+	OpenFlags,
+	OptionalExtension,
+	Row,
+	Transaction,
+	TransactionBehavior,
+	params,
+	types::Value
+};
+use serde::Serialize;
 
-use std::path::Path;
+use std::{
+	env,
+	fmt,
+	error,
+	fs::{self, File, OpenOptions},
+	path::{Path, PathBuf},
+	thread,
+	time::{Duration, Instant}
+};
 
 use crate::rss::{Channel, Item};
 
+///Every change made to the schema, in order.
+///
+///Released migrations must never be edited or reordered, changes go in a new one at the end.
+///The first one uses IF NOT EXISTS since databases from before versioning
+///already have the tables (and are at version 0), the oldest of those
+///don't have the untitled column yet either, see [add_untitled_column].
+const MIGRATIONS: &[&str] = &[
+	//1: channels, items and quickmarks
+	"CREATE TABLE IF NOT EXISTS channels (
+		id INTEGER PRIMARY KEY AUTOINCREMENT,
+		title VARCHAR(256),
+		link VARCHAR(256) UNIQUE,
+		description TEXT,
+		last_build_date VARCHAR
+	);
+	CREATE TABLE IF NOT EXISTS items (
+		hash VARCHAR PRIMARY KEY,
+		title_or_desc VARCHAR(256) NOT NULL,
+		url VARCHAR(256),
+		pub_date VARCHAR,
+		read BOOLEAN NOT NULL,
+		channel INTEGER NOT NULL,
+		untitled BOOLEAN NOT NULL DEFAULT 0,
+		FOREIGN KEY(channel) REFERENCES channels(id) ON DELETE CASCADE
+	);
+	CREATE TABLE IF NOT EXISTS quickmarks (
+		position INTEGER,
+		hash VARCHAR,
+		FOREIGN KEY(hash) REFERENCES items(hash) ON DELETE CASCADE
+	);",
+	//2: items are identified by (channel, hash), so the same post can be in many feeds.
+	//Since hash alone is no longer unique quickmarks can't reference it, instead a trigger
+	//deletes the quickmark once the last row of an item is gone.
+	"CREATE TABLE items_by_channel (
+		hash VARCHAR NOT NULL,
+		title_or_desc VARCHAR(256) NOT NULL,
+		url VARCHAR(256),
+		pub_date VARCHAR,
+		read BOOLEAN NOT NULL,
+		channel INTEGER NOT NULL,
+		untitled BOOLEAN NOT NULL DEFAULT 0,
+		PRIMARY KEY(channel, hash),
+		FOREIGN KEY(channel) REFERENCES channels(id) ON DELETE CASCADE
+	);
+	INSERT INTO items_by_channel (hash, title_or_desc, url, pub_date, read, channel, untitled)
+		SELECT hash, title_or_desc, url, pub_date, read, channel, untitled FROM items;
+	DROP TABLE items;
+	ALTER TABLE items_by_channel RENAME TO items;
+	CREATE INDEX items_hash ON items(hash);
+
+	CREATE TABLE quickmarks_by_hash (
+		position INTEGER,
+		hash VARCHAR
+	);
+	INSERT INTO quickmarks_by_hash (position, hash) SELECT position, hash FROM quickmarks;
+	DROP TABLE quickmarks;
+	ALTER TABLE quickmarks_by_hash RENAME TO quickmarks;
+
+	CREATE TRIGGER delete_quickmark_with_item AFTER DELETE ON items
+	WHEN NOT EXISTS (SELECT 1 FROM items WHERE hash = OLD.hash)
+	BEGIN
+		DELETE FROM quickmarks WHERE hash = OLD.hash;
+	END;",
+	//3: indexes for the usual lookups (unread items, by date, quickmarks both ways)
+	"CREATE INDEX items_read ON items(read);
+	CREATE INDEX items_pub_date ON items(pub_date);
+	CREATE INDEX quickmarks_position ON quickmarks(position);
+	CREATE INDEX quickmarks_hash ON quickmarks(hash);",
+	//4: items get descriptions, contents and a stable id for the full text index to point to
+	//(an implicit rowid may change on VACUUM). The index is an external content FTS5 table,
+	//it doesn't store a copy of the text, triggers keep it in sync with items.
+	"CREATE TABLE items_with_text (
+		id INTEGER PRIMARY KEY,
+		hash VARCHAR NOT NULL,
+		title_or_desc VARCHAR(256) NOT NULL,
+		url VARCHAR(256),
+		pub_date VARCHAR,
+		read BOOLEAN NOT NULL,
+		channel INTEGER NOT NULL,
+		description TEXT,
+		content TEXT,
+		untitled BOOLEAN NOT NULL DEFAULT 0,
+		UNIQUE(channel, hash),
+		FOREIGN KEY(channel) REFERENCES channels(id) ON DELETE CASCADE
+	);
+	INSERT INTO items_with_text (hash, title_or_desc, url, pub_date, read, channel, untitled)
+		SELECT hash, title_or_desc, url, pub_date, read, channel, untitled FROM items;
+	DROP TABLE items;
+	ALTER TABLE items_with_text RENAME TO items;
+	CREATE INDEX items_hash ON items(hash);
+	CREATE INDEX items_read ON items(read);
+	CREATE INDEX items_pub_date ON items(pub_date);
+
+	CREATE TRIGGER delete_quickmark_with_item AFTER DELETE ON items
+	WHEN NOT EXISTS (SELECT 1 FROM items WHERE hash = OLD.hash)
+	BEGIN
+		DELETE FROM quickmarks WHERE hash = OLD.hash;
+	END;
+
+	CREATE VIRTUAL TABLE items_search USING fts5(
+		title_or_desc, description, content,
+		content='items', content_rowid='id'
+	);
+	INSERT INTO items_search(items_search) VALUES('rebuild');
+
+	CREATE TRIGGER items_search_insert AFTER INSERT ON items BEGIN
+		INSERT INTO items_search(rowid, title_or_desc, description, content)
+		VALUES (NEW.id, NEW.title_or_desc, NEW.description, NEW.content);
+	END;
+	CREATE TRIGGER items_search_delete AFTER DELETE ON items BEGIN
+		INSERT INTO items_search(items_search, rowid, title_or_desc, description, content)
+		VALUES ('delete', OLD.id, OLD.title_or_desc, OLD.description, OLD.content);
+	END;
+	CREATE TRIGGER items_search_update AFTER UPDATE OF title_or_desc, description, content ON items BEGIN
+		INSERT INTO items_search(items_search, rowid, title_or_desc, description, content)
+		VALUES ('delete', OLD.id, OLD.title_or_desc, OLD.description, OLD.content);
+		INSERT INTO items_search(rowid, title_or_desc, description, content)
+		VALUES (NEW.id, NEW.title_or_desc, NEW.description, NEW.content);
+	END;",
+	//5: settings (global ones in their own table, per feed ones as columns of channels)
+	//and tombstones for pruned items
+	"CREATE TABLE settings (
+		key VARCHAR PRIMARY KEY,
+		value INTEGER NOT NULL
+	);
+	ALTER TABLE channels ADD COLUMN keep_read_days INTEGER;
+	ALTER TABLE channels ADD COLUMN keep_items INTEGER;
+	CREATE TABLE tombstones (
+		channel INTEGER NOT NULL,
+		hash VARCHAR NOT NULL,
+		PRIMARY KEY(channel, hash),
+		FOREIGN KEY(channel) REFERENCES channels(id) ON DELETE CASCADE
+	) WITHOUT ROWID;",
+	//6: when an item was first fetched and when it was read.
+	//Unknown (NULL) for everything from before, we'd rather not make dates up.
+	"ALTER TABLE items ADD COLUMN first_seen VARCHAR;
+	ALTER TABLE items ADD COLUMN read_at VARCHAR;
+	CREATE INDEX items_read_at ON items(read_at);",
+	//7: dates become unix timestamps (INTEGER columns, text dates that can't be read become NULL).
+	//Each column is replaced by a new one, the indexes on them have to go first.
+	"DROP INDEX items_pub_date;
+	DROP INDEX items_read_at;
+
+	ALTER TABLE items ADD COLUMN pub_date_epoch INTEGER;
+	ALTER TABLE items ADD COLUMN first_seen_epoch INTEGER;
+	ALTER TABLE items ADD COLUMN read_at_epoch INTEGER;
+	UPDATE items SET
+		pub_date_epoch = CAST(strftime('%s', pub_date) AS INTEGER),
+		first_seen_epoch = CAST(strftime('%s', first_seen) AS INTEGER),
+		read_at_epoch = CAST(strftime('%s', read_at) AS INTEGER);
+	ALTER TABLE items DROP COLUMN pub_date;
+	ALTER TABLE items DROP COLUMN first_seen;
+	ALTER TABLE items DROP COLUMN read_at;
+	ALTER TABLE items RENAME COLUMN pub_date_epoch TO pub_date;
+	ALTER TABLE items RENAME COLUMN first_seen_epoch TO first_seen;
+	ALTER TABLE items RENAME COLUMN read_at_epoch TO read_at;
+
+	ALTER TABLE channels ADD COLUMN last_build_date_epoch INTEGER;
+	UPDATE channels SET last_build_date_epoch = CAST(strftime('%s', last_build_date) AS INTEGER);
+	ALTER TABLE channels DROP COLUMN last_build_date;
+	ALTER TABLE channels RENAME COLUMN last_build_date_epoch TO last_build_date;
+
+	CREATE INDEX items_read_at ON items(read_at);
+	CREATE INDEX items_sort_date ON items(COALESCE(pub_date, first_seen));",
+	//8: the v1 views, for people querying the database (see Views in the module docs)
+	"CREATE VIEW v1_unread_items AS
+	SELECT quickmarks.position AS quickmark,
+		items.hash AS hash,
+		items.title_or_desc AS title,
+		items.url AS link,
+		items.description AS description,
+		items.content AS content,
+		strftime('%Y-%m-%dT%H:%M:%SZ', items.pub_date, 'unixepoch') AS published,
+		strftime('%Y-%m-%dT%H:%M:%SZ', items.first_seen, 'unixepoch') AS first_seen,
+		channels.title AS feed_title,
+		channels.link AS feed_link
+	FROM items
+		INNER JOIN channels ON channels.id = items.channel
+		LEFT JOIN quickmarks ON quickmarks.hash = items.hash
+	WHERE items.read = 0;
+
+	CREATE VIEW v1_feed_stats AS
+	SELECT channels.title AS feed_title,
+		channels.link AS feed_link,
+		channels.description AS description,
+		strftime('%Y-%m-%dT%H:%M:%SZ', channels.last_build_date, 'unixepoch') AS last_build_date,
+		COUNT(items.id) AS items,
+		COUNT(CASE WHEN items.read = 0 THEN 1 END) AS unread,
+		COUNT(CASE WHEN items.read = 1 THEN 1 END) AS read,
+		strftime('%Y-%m-%dT%H:%M:%SZ', MAX(items.pub_date), 'unixepoch') AS last_published,
+		strftime('%Y-%m-%dT%H:%M:%SZ', MAX(items.read_at), 'unixepoch') AS last_read
+	FROM channels LEFT JOIN items ON items.channel = channels.id
+	GROUP BY channels.id;
+
+	CREATE VIEW v1_history AS
+	SELECT items.hash AS hash,
+		items.title_or_desc AS title,
+		items.url AS link,
+		strftime('%Y-%m-%dT%H:%M:%SZ', items.read_at, 'unixepoch') AS read_at,
+		strftime('%Y-%m-%dT%H:%M:%SZ', items.pub_date, 'unixepoch') AS published,
+		channels.title AS feed_title,
+		channels.link AS feed_link
+	FROM items INNER JOIN channels ON channels.id = items.channel
+	WHERE items.read = 1;",
+	//9: starred items, and their view
+	"ALTER TABLE items ADD COLUMN starred_at INTEGER;
+	CREATE INDEX items_starred_at ON items(starred_at);
+
+	CREATE VIEW v1_starred_items AS
+	SELECT items.hash AS hash,
+		items.title_or_desc AS title,
+		items.url AS link,
+		items.description AS description,
+		items.content AS content,
+		strftime('%Y-%m-%dT%H:%M:%SZ', items.starred_at, 'unixepoch') AS starred_at,
+		strftime('%Y-%m-%dT%H:%M:%SZ', items.pub_date, 'unixepoch') AS published,
+		items.read AS read,
+		channels.title AS feed_title,
+		channels.link AS feed_link
+	FROM items INNER JOIN channels ON channels.id = items.channel
+	WHERE items.starred_at IS NOT NULL;",
+	//10: item ids are never reused, so the ones the journal refers to can't be taken by new items
+	//(see Journal in the module docs). The table is rebuilt with AUTOINCREMENT and the same ids,
+	//the legacy rename leaves the views on items alone.
+	"PRAGMA legacy_alter_table = ON;
+	CREATE TABLE items_autoincrement (
+		id INTEGER PRIMARY KEY AUTOINCREMENT,
+		hash VARCHAR NOT NULL,
+		title_or_desc VARCHAR(256) NOT NULL,
+		url VARCHAR(256),
+		pub_date INTEGER,
+		read BOOLEAN NOT NULL,
+		channel INTEGER NOT NULL,
+		description TEXT,
+		content TEXT,
+		untitled BOOLEAN NOT NULL DEFAULT 0,
+		first_seen INTEGER,
+		read_at INTEGER,
+		starred_at INTEGER,
+		UNIQUE(channel, hash),
+		FOREIGN KEY(channel) REFERENCES channels(id) ON DELETE CASCADE
+	);
+	INSERT INTO items_autoincrement (id, hash, title_or_desc, url, pub_date, read, channel, description, content,
+		untitled, first_seen, read_at, starred_at)
+		SELECT id, hash, title_or_desc, url, pub_date, read, channel, description, content,
+			untitled, first_seen, read_at, starred_at FROM items;
+	DROP TABLE items;
+	ALTER TABLE items_autoincrement RENAME TO items;
+	PRAGMA legacy_alter_table = OFF;
+
+	CREATE INDEX items_hash ON items(hash);
+	CREATE INDEX items_read ON items(read);
+	CREATE INDEX items_read_at ON items(read_at);
+	CREATE INDEX items_sort_date ON items(COALESCE(pub_date, first_seen));
+	CREATE INDEX items_starred_at ON items(starred_at);
+
+	CREATE TRIGGER delete_quickmark_with_item AFTER DELETE ON items
+	WHEN NOT EXISTS (SELECT 1 FROM items WHERE hash = OLD.hash)
+	BEGIN
+		DELETE FROM quickmarks WHERE hash = OLD.hash;
+	END;
+	CREATE TRIGGER items_search_insert AFTER INSERT ON items BEGIN
+		INSERT INTO items_search(rowid, title_or_desc, description, content)
+		VALUES (NEW.id, NEW.title_or_desc, NEW.description, NEW.content);
+	END;
+	CREATE TRIGGER items_search_delete AFTER DELETE ON items BEGIN
+		INSERT INTO items_search(items_search, rowid, title_or_desc, description, content)
+		VALUES ('delete', OLD.id, OLD.title_or_desc, OLD.description, OLD.content);
+	END;
+	CREATE TRIGGER items_search_update AFTER UPDATE OF title_or_desc, description, content ON items BEGIN
+		INSERT INTO items_search(items_search, rowid, title_or_desc, description, content)
+		VALUES ('delete', OLD.id, OLD.title_or_desc, OLD.description, OLD.content);
+		INSERT INTO items_search(rowid, title_or_desc, description, content)
+		VALUES (NEW.id, NEW.title_or_desc, NEW.description, NEW.content);
+	END;",
+	//11: the journal, for undo (see Journal in the module docs).
+	//Triggers record every change while the newest entry is recording, for inserts the key
+	//of the new row, otherwise the row as it was (only the columns commands change for items).
+	"CREATE TABLE journal (
+		id INTEGER PRIMARY KEY AUTOINCREMENT,
+		command VARCHAR NOT NULL,
+		done_at INTEGER NOT NULL,
+		recording BOOLEAN NOT NULL DEFAULT 1
+	);
+	CREATE TABLE journal_changes (
+		id INTEGER PRIMARY KEY,
+		operation INTEGER NOT NULL,
+		target VARCHAR NOT NULL,
+		action VARCHAR NOT NULL,
+		data TEXT NOT NULL,
+		FOREIGN KEY(operation) REFERENCES journal(id) ON DELETE CASCADE
+	);
+	CREATE INDEX journal_changes_operation ON journal_changes(operation);
+
+	CREATE TRIGGER journal_channels_insert AFTER INSERT ON channels
+	WHEN (SELECT recording FROM journal ORDER BY id DESC LIMIT 1)
+	BEGIN
+		INSERT INTO journal_changes (operation, target, action, data)
+		VALUES ((SELECT MAX(id) FROM journal), 'channels', 'insert', json_object('id', NEW.id));
+	END;
+	CREATE TRIGGER journal_channels_update AFTER UPDATE ON channels
+	WHEN (SELECT recording FROM journal ORDER BY id DESC LIMIT 1)
+	BEGIN
+		INSERT INTO journal_changes (operation, target, action, data)
+		VALUES ((SELECT MAX(id) FROM journal), 'channels', 'update', json_object(
+			'id', OLD.id, 'title', OLD.title, 'link', OLD.link, 'description', OLD.description,
+			'keep_read_days', OLD.keep_read_days, 'keep_items', OLD.keep_items,
+			'last_build_date', OLD.last_build_date
+		));
+	END;
+	CREATE TRIGGER journal_channels_delete AFTER DELETE ON channels
+	WHEN (SELECT recording FROM journal ORDER BY id DESC LIMIT 1)
+	BEGIN
+		INSERT INTO journal_changes (operation, target, action, data)
+		VALUES ((SELECT MAX(id) FROM journal), 'channels', 'delete', json_object(
+			'id', OLD.id, 'title', OLD.title, 'link', OLD.link, 'description', OLD.description,
+			'keep_read_days', OLD.keep_read_days, 'keep_items', OLD.keep_items,
+			'last_build_date', OLD.last_build_date
+		));
+	END;
+
+	CREATE TRIGGER journal_items_insert AFTER INSERT ON items
+	WHEN (SELECT recording FROM journal ORDER BY id DESC LIMIT 1)
+	BEGIN
+		INSERT INTO journal_changes (operation, target, action, data)
+		VALUES ((SELECT MAX(id) FROM journal), 'items', 'insert', json_object('id', NEW.id));
+	END;
+	CREATE TRIGGER journal_items_update AFTER UPDATE OF read, read_at, starred_at ON items
+	WHEN (SELECT recording FROM journal ORDER BY id DESC LIMIT 1)
+		AND (OLD.read IS NOT NEW.read OR OLD.read_at IS NOT NEW.read_at OR OLD.starred_at IS NOT NEW.starred_at)
+	BEGIN
+		INSERT INTO journal_changes (operation, target, action, data)
+		VALUES ((SELECT MAX(id) FROM journal), 'items', 'update', json_object(
+			'id', OLD.id, 'read', OLD.read, 'read_at', OLD.read_at, 'starred_at', OLD.starred_at
+		));
+	END;
+	CREATE TRIGGER journal_items_delete AFTER DELETE ON items
+	WHEN (SELECT recording FROM journal ORDER BY id DESC LIMIT 1)
+	BEGIN
+		INSERT INTO journal_changes (operation, target, action, data)
+		VALUES ((SELECT MAX(id) FROM journal), 'items', 'delete', json_object(
+			'id', OLD.id, 'hash', OLD.hash, 'title_or_desc', OLD.title_or_desc, 'url', OLD.url,
+			'pub_date', OLD.pub_date, 'read', OLD.read, 'channel', OLD.channel,
+			'description', OLD.description, 'content', OLD.content, 'first_seen', OLD.first_seen,
+			'read_at', OLD.read_at, 'starred_at', OLD.starred_at, 'untitled', OLD.untitled
+		));
+	END;
+
+	CREATE TRIGGER journal_tombstones_insert AFTER INSERT ON tombstones
+	WHEN (SELECT recording FROM journal ORDER BY id DESC LIMIT 1)
+	BEGIN
+		INSERT INTO journal_changes (operation, target, action, data)
+		VALUES ((SELECT MAX(id) FROM journal), 'tombstones', 'insert',
+			json_object('channel', NEW.channel, 'hash', NEW.hash));
+	END;
+	CREATE TRIGGER journal_tombstones_delete AFTER DELETE ON tombstones
+	WHEN (SELECT recording FROM journal ORDER BY id DESC LIMIT 1)
+	BEGIN
+		INSERT INTO journal_changes (operation, target, action, data)
+		VALUES ((SELECT MAX(id) FROM journal), 'tombstones', 'delete',
+			json_object('channel', OLD.channel, 'hash', OLD.hash));
+	END;
+
+	CREATE TRIGGER journal_settings_insert AFTER INSERT ON settings
+	WHEN (SELECT recording FROM journal ORDER BY id DESC LIMIT 1)
+	BEGIN
+		INSERT INTO journal_changes (operation, target, action, data)
+		VALUES ((SELECT MAX(id) FROM journal), 'settings', 'insert', json_object('key', NEW.key));
+	END;
+	CREATE TRIGGER journal_settings_update AFTER UPDATE ON settings
+	WHEN (SELECT recording FROM journal ORDER BY id DESC LIMIT 1)
+	BEGIN
+		INSERT INTO journal_changes (operation, target, action, data)
+		VALUES ((SELECT MAX(id) FROM journal), 'settings', 'update', json_object('key', OLD.key, 'value', OLD.value));
+	END;
+	CREATE TRIGGER journal_settings_delete AFTER DELETE ON settings
+	WHEN (SELECT recording FROM journal ORDER BY id DESC LIMIT 1)
+	BEGIN
+		INSERT INTO journal_changes (operation, target, action, data)
+		VALUES ((SELECT MAX(id) FROM journal), 'settings', 'delete', json_object('key', OLD.key, 'value', OLD.value));
+	END;",
+	//12: snoozed items, out of the queue until snoozed_until. The journal triggers on items
+	//are recreated to record the new column.
+	"ALTER TABLE items ADD COLUMN snoozed_until INTEGER;
+	CREATE INDEX items_snoozed_until ON items(snoozed_until);
+
+	DROP TRIGGER journal_items_update;
+	CREATE TRIGGER journal_items_update AFTER UPDATE OF read, read_at, starred_at, snoozed_until ON items
+	WHEN (SELECT recording FROM journal ORDER BY id DESC LIMIT 1)
+		AND (OLD.read IS NOT NEW.read OR OLD.read_at IS NOT NEW.read_at OR OLD.starred_at IS NOT NEW.starred_at
+			OR OLD.snoozed_until IS NOT NEW.snoozed_until)
+	BEGIN
+		INSERT INTO journal_changes (operation, target, action, data)
+		VALUES ((SELECT MAX(id) FROM journal), 'items', 'update', json_object(
+			'id', OLD.id, 'read', OLD.read, 'read_at', OLD.read_at, 'starred_at', OLD.starred_at,
+			'snoozed_until', OLD.snoozed_until
+		));
+	END;
+	DROP TRIGGER journal_items_delete;
+	CREATE TRIGGER journal_items_delete AFTER DELETE ON items
+	WHEN (SELECT recording FROM journal ORDER BY id DESC LIMIT 1)
+	BEGIN
+		INSERT INTO journal_changes (operation, target, action, data)
+		VALUES ((SELECT MAX(id) FROM journal), 'items', 'delete', json_object(
+			'id', OLD.id, 'hash', OLD.hash, 'title_or_desc', OLD.title_or_desc, 'url', OLD.url,
+			'pub_date', OLD.pub_date, 'read', OLD.read, 'channel', OLD.channel,
+			'description', OLD.description, 'content', OLD.content, 'first_seen', OLD.first_seen,
+			'read_at', OLD.read_at, 'starred_at', OLD.starred_at, 'snoozed_until', OLD.snoozed_until,
+			'untitled', OLD.untitled
+		));
+	END;
+
+	CREATE VIEW v1_snoozed_items AS
+	SELECT items.hash AS hash,
+		items.title_or_desc AS title,
+		items.url AS link,
+		items.description AS description,
+		items.content AS content,
+		strftime('%Y-%m-%dT%H:%M:%SZ', items.snoozed_until, 'unixepoch') AS snoozed_until,
+		strftime('%Y-%m-%dT%H:%M:%SZ', items.pub_date, 'unixepoch') AS published,
+		channels.title AS feed_title,
+		channels.link AS feed_link
+	FROM items INNER JOIN channels ON channels.id = items.channel
+	WHERE items.read = 0 AND items.snoozed_until > CAST(strftime('%s', 'now') AS INTEGER);",
+	//13: expiring unread items: the per feed setting, when an item came back to the queue
+	//and a flag on the items corkboard marked as read itself.
+	//The journal triggers on both tables are recreated to record them.
+	"ALTER TABLE channels ADD COLUMN expire_unread_days INTEGER;
+	ALTER TABLE items ADD COLUMN queued_at INTEGER;
+	ALTER TABLE items ADD COLUMN auto_read BOOLEAN NOT NULL DEFAULT 0;
+
+	DROP TRIGGER journal_channels_update;
+	CREATE TRIGGER journal_channels_update AFTER UPDATE ON channels
+	WHEN (SELECT recording FROM journal ORDER BY id DESC LIMIT 1)
+	BEGIN
+		INSERT INTO journal_changes (operation, target, action, data)
+		VALUES ((SELECT MAX(id) FROM journal), 'channels', 'update', json_object(
+			'id', OLD.id, 'title', OLD.title, 'link', OLD.link, 'description', OLD.description,
+			'keep_read_days', OLD.keep_read_days, 'keep_items', OLD.keep_items,
+			'last_build_date', OLD.last_build_date, 'expire_unread_days', OLD.expire_unread_days
+		));
+	END;
+	DROP TRIGGER journal_channels_delete;
+	CREATE TRIGGER journal_channels_delete AFTER DELETE ON channels
+	WHEN (SELECT recording FROM journal ORDER BY id DESC LIMIT 1)
+	BEGIN
+		INSERT INTO journal_changes (operation, target, action, data)
+		VALUES ((SELECT MAX(id) FROM journal), 'channels', 'delete', json_object(
+			'id', OLD.id, 'title', OLD.title, 'link', OLD.link, 'description', OLD.description,
+			'keep_read_days', OLD.keep_read_days, 'keep_items', OLD.keep_items,
+			'last_build_date', OLD.last_build_date, 'expire_unread_days', OLD.expire_unread_days
+		));
+	END;
+
+	DROP TRIGGER journal_items_update;
+	CREATE TRIGGER journal_items_update
+	AFTER UPDATE OF read, read_at, starred_at, snoozed_until, queued_at, auto_read ON items
+	WHEN (SELECT recording FROM journal ORDER BY id DESC LIMIT 1)
+		AND (OLD.read IS NOT NEW.read OR OLD.read_at IS NOT NEW.read_at OR OLD.starred_at IS NOT NEW.starred_at
+			OR OLD.snoozed_until IS NOT NEW.snoozed_until OR OLD.queued_at IS NOT NEW.queued_at
+			OR OLD.auto_read IS NOT NEW.auto_read)
+	BEGIN
+		INSERT INTO journal_changes (operation, target, action, data)
+		VALUES ((SELECT MAX(id) FROM journal), 'items', 'update', json_object(
+			'id', OLD.id, 'read', OLD.read, 'read_at', OLD.read_at, 'starred_at', OLD.starred_at,
+			'snoozed_until', OLD.snoozed_until, 'queued_at', OLD.queued_at, 'auto_read', OLD.auto_read
+		));
+	END;
+	DROP TRIGGER journal_items_delete;
+	CREATE TRIGGER journal_items_delete AFTER DELETE ON items
+	WHEN (SELECT recording FROM journal ORDER BY id DESC LIMIT 1)
+	BEGIN
+		INSERT INTO journal_changes (operation, target, action, data)
+		VALUES ((SELECT MAX(id) FROM journal), 'items', 'delete', json_object(
+			'id', OLD.id, 'hash', OLD.hash, 'title_or_desc', OLD.title_or_desc, 'url', OLD.url,
+			'pub_date', OLD.pub_date, 'read', OLD.read, 'channel', OLD.channel,
+			'description', OLD.description, 'content', OLD.content, 'first_seen', OLD.first_seen,
+			'read_at', OLD.read_at, 'starred_at', OLD.starred_at, 'snoozed_until', OLD.snoozed_until,
+			'queued_at', OLD.queued_at, 'auto_read', OLD.auto_read, 'untitled', OLD.untitled
+		));
+	END;",
+	//14: quickmarks get constraints, a position holds a single item and an item has a single position.
+	//Rows breaking them (which doctor used to repair) are dropped, every item keeps its lowest position.
+	//The trigger and the view using the table are recreated as they were.
+	"DROP TRIGGER delete_quickmark_with_item;
+	DROP VIEW v1_unread_items;
+
+	CREATE TABLE quickmarks_unique (
+		position INTEGER PRIMARY KEY CHECK (position > 0),
+		hash VARCHAR NOT NULL UNIQUE
+	);
+	INSERT OR IGNORE INTO quickmarks_unique (position, hash)
+		SELECT position, hash FROM quickmarks
+		WHERE position > 0 AND hash IS NOT NULL
+		ORDER BY position, rowid;
+	DROP TABLE quickmarks;
+	ALTER TABLE quickmarks_unique RENAME TO quickmarks;
+
+	CREATE TRIGGER delete_quickmark_with_item AFTER DELETE ON items
+	WHEN NOT EXISTS (SELECT 1 FROM items WHERE hash = OLD.hash)
+	BEGIN
+		DELETE FROM quickmarks WHERE hash = OLD.hash;
+	END;
+
+	CREATE VIEW v1_unread_items AS
+	SELECT quickmarks.position AS quickmark,
+		items.hash AS hash,
+		items.title_or_desc AS title,
+		items.url AS link,
+		items.description AS description,
+		items.content AS content,
+		strftime('%Y-%m-%dT%H:%M:%SZ', items.pub_date, 'unixepoch') AS published,
+		strftime('%Y-%m-%dT%H:%M:%SZ', items.first_seen, 'unixepoch') AS first_seen,
+		channels.title AS feed_title,
+		channels.link AS feed_link
+	FROM items
+		INNER JOIN channels ON channels.id = items.channel
+		LEFT JOIN quickmarks ON quickmarks.hash = items.hash
+	WHERE items.read = 0;"
+];
+
+///Unread items that have been in the queue for longer than expire_unread_days allows.
+///
+///A feed's own setting wins over the global one, a negative value (or none at all) means never.
+///Time in the queue counts from when the item came back to it (unmarked, unstarred or at the
+///end of a snooze), or else from when it was first seen (or published, if seen before we kept track of that).
+///An item in several feeds is expired if any of them says so, starred items never are.
+const EXPIRED_ITEMS: &str = "SELECT DISTINCT hash FROM (
+		SELECT items.hash, COALESCE(items.queued_at, items.first_seen, items.pub_date) AS queued_since,
+			COALESCE(channels.expire_unread_days, (SELECT value FROM settings WHERE key = 'expire_unread_days'))
+				AS expire_unread_days
+		FROM items INNER JOIN channels ON channels.id = items.channel
+		WHERE items.read = 0 AND items.starred_at IS NULL
+	)
+	WHERE expire_unread_days >= 0
+		AND queued_since < CAST(strftime('%s', 'now') AS INTEGER) - expire_unread_days * 86400;";
+
+///Read items that the retention settings no longer allow us to keep.
+///
+///A feed's own setting wins over the global one, a negative value means no limit.
+///Age is counted from when the item was read, or (if read before we kept track of that)
+///from when it was published or first seen.
+///Items are ranked per feed from newest to oldest, ones without any date count as the oldest.
+///Starred items are never pruned (but still count for keep_items).
+const PRUNABLE_ITEMS: &str = "SELECT id FROM (
+		SELECT items.id, items.read, items.starred_at, COALESCE(items.read_at, items.pub_date, items.first_seen) AS read_since,
+			ROW_NUMBER() OVER (
+				PARTITION BY items.channel
+				ORDER BY COALESCE(items.pub_date, items.first_seen) DESC, items.id DESC
+			) AS newness,
+			COALESCE(channels.keep_read_days, (SELECT value FROM settings WHERE key = 'keep_read_days')) AS keep_read_days,
+			COALESCE(channels.keep_items, (SELECT value FROM settings WHERE key = 'keep_items')) AS keep_items
+		FROM items INNER JOIN channels ON channels.id = items.channel
+	)
+	WHERE read = 1 AND starred_at IS NULL AND (
+		(keep_read_days >= 0 AND read_since < CAST(strftime('%s', 'now') AS INTEGER) - keep_read_days * 86400)
+		OR (keep_items >= 0 AND newness > keep_items)
+	);";
+
+///Columns read by [item_from_row], in order. Queries returning items start with these.
+const ITEM_COLUMNS: &str = "items.hash, items.title_or_desc, items.url, items.pub_date, items.read, \
+	items.description, items.content, items.first_seen, items.read_at, items.starred_at, items.snoozed_until, \
+	items.auto_read, items.untitled";
+
+///Index of the first column after [ITEM_COLUMNS]
+const AFTER_ITEM_COLUMNS: usize = 13;
+
+///Build an item from a row that starts with [ITEM_COLUMNS]
+fn item_from_row(row: &Row) -> rusqlite::Result<Item> {
+	Ok(Item {
+		title_or_description_hash: row.get(0)?,
+		title_or_description: row.get(1)?,
+		link: row.get(2)?,
+		pub_date: from_timestamp(row.get(3)?),
+		read: row.get(4)?,
+		description: row.get(5)?,
+		content: row.get(6)?,
+		first_seen: from_timestamp(row.get(7)?),
+		read_at: from_timestamp(row.get(8)?),
+		starred_at: from_timestamp(row.get(9)?),
+		snoozed_until: from_timestamp(row.get(10)?),
+		auto_read: row.get(11)?,
+		untitled: row.get(12)?
+	})
+}
+
+///How a date is stored, seconds since the unix epoch
+fn timestamp(date: Option<DateTime<Utc>>) -> Option<i64> {
+	date.map(|d| d.timestamp())
+}
+
+///A stored date back as a date, a timestamp out of chrono's range is taken as no date
+fn from_timestamp(seconds: Option<i64>) -> Option<DateTime<Utc>> {
+	seconds.and_then(|s| Utc.timestamp_opt(s, 0).single())
+}
+
+///Restricts which items a query returns, every field left as None lets everything through.
+#[derive(Debug, Default)]
+pub struct ItemFilter {
+	///Only read (true) or only unread (false) items
+	pub read: Option<bool>,
+	///Only items from the feed with this url (with or without the protocol)
+	pub feed: Option<String>,
+	///Only items published (or for the history, read) at or after this date
+	pub since: Option<DateTime<Utc>>,
+	///Only items published (or for the history, read) at or before this date
+	pub until: Option<DateTime<Utc>>
+}
+
+///Something the user can configure with __config__.
+///
+///Every setting is stored as an integer, either globally or (for some) per feed,
+///a feed's own value wins over the global one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+	///Items read more than this many days ago are pruned
+	KeepReadDays,
+	///Read items past this many (counting all items, newest first) are pruned, per feed
+	KeepItems,
+	///Prune at the end of every __up__ (global only)
+	PruneAfterUp,
+	///Operations done more than this many days ago can no longer be undone (global only)
+	KeepJournalDays,
+	///Unread items in the queue for more than this many days are marked as read on __up__
+	ExpireUnreadDays
+}
+impl Setting {
+	///Every setting, in the order they are listed
+	pub const ALL: &'static [Setting] = &[
+		Setting::KeepReadDays, Setting::KeepItems, Setting::PruneAfterUp, Setting::KeepJournalDays,
+		Setting::ExpireUnreadDays
+	];
+
+	///Name of the setting, as the user writes it and as it's stored
+	pub fn name(self) -> &'static str {
+		match self {
+			Setting::KeepReadDays => "keep_read_days",
+			Setting::KeepItems => "keep_items",
+			Setting::PruneAfterUp => "prune_after_up",
+			Setting::KeepJournalDays => "keep_journal_days",
+			Setting::ExpireUnreadDays => "expire_unread_days"
+		}
+	}
+
+	///Whether feeds can have their own value (then it's also a column of channels)
+	pub fn per_feed(self) -> bool {
+		!matches!(self, Setting::PruneAfterUp | Setting::KeepJournalDays)
+	}
+
+	///Turn what the user wrote into the stored value
+	pub fn parse_value(self, value: &str) -> Result<i64, SettingError> {
+		let invalid = || SettingError::InvalidValue(self.name(), value.to_string());
+		match self {
+			Setting::KeepReadDays | Setting::KeepItems | Setting::KeepJournalDays | Setting::ExpireUnreadDays => match value {
+				"never" => Ok(-1),
+				_ => value.parse::<u32>().map(i64::from).map_err(|_| invalid())
+			},
+			Setting::PruneAfterUp => match value {
+				"true" | "on" => Ok(1),
+				"false" | "off" => Ok(0),
+				_ => Err(invalid())
+			}
+		}
+	}
+
+	///Turn a stored value into what the user would write
+	pub fn format_value(self, value: i64) -> String {
+		match self {
+			Setting::KeepReadDays | Setting::KeepItems | Setting::KeepJournalDays | Setting::ExpireUnreadDays
+				if value < 0 => String::from("never"),
+			Setting::PruneAfterUp => (value != 0).to_string(),
+			_ => value.to_string()
+		}
+	}
+}
+impl std::str::FromStr for Setting {
+	type Err = SettingError;
+	fn from_str(name: &str) -> Result<Self, Self::Err> {
+		Setting::ALL.iter()
+			.find(|setting| setting.name() == name)
+			.copied()
+			.ok_or_else(|| SettingError::UnknownSetting(name.to_string()))
+	}
+}
+
+///Errors about reading or changing settings
+#[derive(Debug)]
+pub enum SettingError {
+	UnknownSetting(String),
+	///The value can't be used for the setting (setting, value)
+	InvalidValue(&'static str, String),
+	///A global only setting was given a feed
+	NotPerFeed(&'static str),
+	///No subscribed feed has this url
+	UnknownFeed(String)
+}
+impl fmt::Display for SettingError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			SettingError::UnknownSetting(name) => write!(f, "There is no setting called {name}"),
+			SettingError::InvalidValue(name, value) => write!(f, "\"{value}\" is not a valid value for {name}"),
+			SettingError::NotPerFeed(name) => write!(f, "{name} can't be set for a single feed"),
+			SettingError::UnknownFeed(url) => write!(f, "Not subscribed to any feed at {url}")
+		}
+	}
+}
+impl error::Error for SettingError {}
+
+///How the user points at an item: by quickmark, hash, link or place in the history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ItemRef {
+	Quickmark(usize),
+	///Position in the reading history (see [Database::history]), 1 is the item read last
+	HistoryPosition(usize),
+	Hash(String),
+	///The link of the item, with or without the protocol (may be several items)
+	Url(String)
+}
+impl From<&str> for ItemRef {
+	///Numbers are quickmarks, 64 hexadecimal digits a hash, anything else a link.
+	fn from(item: &str) -> Self {
+		if let Ok(position) = item.parse() {
+			ItemRef::Quickmark(position)
+		} else if item.len() == 64 && item.chars().all(|c| c.is_ascii_hexdigit()) {
+			ItemRef::Hash(item.to_ascii_lowercase())
+		} else {
+			ItemRef::Url(item.to_string())
+		}
+	}
+}
+impl fmt::Display for ItemRef {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ItemRef::Quickmark(position) => write!(f, "quickmark {position}"),
+			ItemRef::HistoryPosition(position) => write!(f, "position {position} in the history"),
+			ItemRef::Hash(hash) => write!(f, "hash {hash}"),
+			ItemRef::Url(url) => write!(f, "link {url}")
+		}
+	}
+}
+
+///Errors about finding the items the user pointed at
+#[derive(Debug)]
+pub enum ItemRefError {
+	///No item matches
+	NotFound(ItemRef)
+}
+impl fmt::Display for ItemRefError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ItemRefError::NotFound(item) => write!(f, "No item has the {item}")
+		}
+	}
+}
+impl error::Error for ItemRefError {}
+
+///Gives a quickmark to every item in the unread queue (unread, not starred nor snoozed) that has none,
+///after the existing ones, in publishing order.
+const ADD_MISSING_QUICKMARKS: &str = "INSERT INTO quickmarks (position, hash)
+	SELECT (SELECT COALESCE(MAX(position), 0) FROM quickmarks)
+		+ ROW_NUMBER() OVER (ORDER BY sort_date IS NULL, sort_date, first_id), hash
+	FROM (
+		SELECT hash, MIN(COALESCE(pub_date, first_seen)) AS sort_date, MIN(id) AS first_id FROM items
+		WHERE read=0 AND starred_at IS NULL AND hash NOT IN (SELECT hash FROM quickmarks)
+		GROUP BY hash
+		HAVING MAX(snoozed_until) IS NULL OR MAX(snoozed_until) <= CAST(strftime('%s', 'now') AS INTEGER)
+	);";
+
+///How to revert each kind of change in the journal: (table, action, statement).
+///
+///The statements get the data recorded by the journal triggers (see migrations 11, 12 and 13) as ?1.
+///Keys missing from older entries come out as NULL.
+const UNDO_CHANGES: &[(&str, &str, &str)] = &[
+	("channels", "insert", "DELETE FROM channels WHERE id = json_extract(?1, '$.id');"),
+	("channels", "update", "UPDATE channels SET
+		title = json_extract(?1, '$.title'),
+		link = json_extract(?1, '$.link'),
+		description = json_extract(?1, '$.description'),
+		keep_read_days = json_extract(?1, '$.keep_read_days'),
+		keep_items = json_extract(?1, '$.keep_items'),
+		last_build_date = json_extract(?1, '$.last_build_date'),
+		expire_unread_days = json_extract(?1, '$.expire_unread_days')
+	WHERE id = json_extract(?1, '$.id');"),
+	("channels", "delete", "INSERT INTO channels (id, title, link, description, keep_read_days, keep_items, last_build_date,
+		expire_unread_days)
+	VALUES (json_extract(?1, '$.id'), json_extract(?1, '$.title'), json_extract(?1, '$.link'),
+		json_extract(?1, '$.description'), json_extract(?1, '$.keep_read_days'), json_extract(?1, '$.keep_items'),
+		json_extract(?1, '$.last_build_date'), json_extract(?1, '$.expire_unread_days'));"),
+	("items", "insert", "DELETE FROM items WHERE id = json_extract(?1, '$.id');"),
+	("items", "update", "UPDATE items SET
+		read = json_extract(?1, '$.read'),
+		read_at = json_extract(?1, '$.read_at'),
+		starred_at = json_extract(?1, '$.starred_at'),
+		snoozed_until = json_extract(?1, '$.snoozed_until'),
+		queued_at = json_extract(?1, '$.queued_at'),
+		auto_read = COALESCE(json_extract(?1, '$.auto_read'), 0)
+	WHERE id = json_extract(?1, '$.id');"),
+	("items", "delete", "INSERT INTO items (id, hash, title_or_desc, url, pub_date, read, channel, description, content,
+		first_seen, read_at, starred_at, snoozed_until, queued_at, auto_read, untitled)
+	VALUES (json_extract(?1, '$.id'), json_extract(?1, '$.hash'), json_extract(?1, '$.title_or_desc'),
+		json_extract(?1, '$.url'), json_extract(?1, '$.pub_date'), json_extract(?1, '$.read'),
+		json_extract(?1, '$.channel'), json_extract(?1, '$.description'), json_extract(?1, '$.content'),
+		json_extract(?1, '$.first_seen'), json_extract(?1, '$.read_at'), json_extract(?1, '$.starred_at'),
+		json_extract(?1, '$.snoozed_until'), json_extract(?1, '$.queued_at'),
+		COALESCE(json_extract(?1, '$.auto_read'), 0),
+		COALESCE(json_extract(?1, '$.untitled'), 0));"),
+	("tombstones", "insert", "DELETE FROM tombstones
+	WHERE channel = json_extract(?1, '$.channel') AND hash = json_extract(?1, '$.hash');"),
+	("tombstones", "delete", "INSERT OR IGNORE INTO tombstones (channel, hash)
+	VALUES (json_extract(?1, '$.channel'), json_extract(?1, '$.hash'));"),
+	("settings", "insert", "DELETE FROM settings WHERE key = json_extract(?1, '$.key');"),
+	("settings", "update", "UPDATE settings SET value = json_extract(?1, '$.value') WHERE key = json_extract(?1, '$.key');"),
+	("settings", "delete", "INSERT INTO settings (key, value) VALUES (json_extract(?1, '$.key'), json_extract(?1, '$.value'));")
+];
+
+///How long operations stay in the journal (in days) when keep_journal_days isn't set
+pub const JOURNAL_DAYS_DEFAULT: i64 = 30;
+
+///An operation recorded in the journal, that __undo__ can revert.
+#[derive(Debug, Clone, Serialize)]
+pub struct JournalEntry {
+	///The command as corkboard describes it, say "mark 3" or "remove example.com"
+	pub command: String,
+	///When it was done
+	pub done_at: DateTime<Utc>,
+	///How many rows it changed (an item in several feeds is a row per feed)
+	pub changes: usize
+}
+
+///Errors from __undo__
+#[derive(Debug)]
+pub enum UndoError {
+	///Asked to undo more operations than the journal has (asked, in the journal)
+	TooFew(usize, usize),
+	///A change in the journal that we don't know how to revert (table, action)
+	UnknownChange(String, String)
+}
+impl fmt::Display for UndoError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			UndoError::TooFew(_, 0) => write!(f, "Nothing to undo"),
+			UndoError::TooFew(asked, available) =>
+				write!(f, "Can't undo {asked} operations, only {available} are in the journal"),
+			UndoError::UnknownChange(target, action) =>
+				write!(f, "Don't know how to undo a {action} in {target}, was the journal written by a newer corkboard?")
+		}
+	}
+}
+impl error::Error for UndoError {}
+
+///An inconsistency that __doctor__ looks for.
+struct Check {
+	///What is wrong, as shown to the user
+	description: &'static str,
+	///Query returning how many rows have the problem
+	count: &'static str,
+	///Statement that fixes the problem
+	repair: &'static str
+}
+
+///Everything __doctor__ checks, in the order the repairs have to run
+///(dangling rows are removed before looking for unread items without a mark).
+const CHECKS: &[Check] = &[
+	Check {
+		description: "items whose channel no longer exists",
+		count: "SELECT COUNT(*) FROM items WHERE channel NOT IN (SELECT id FROM channels);",
+		repair: "DELETE FROM items WHERE channel NOT IN (SELECT id FROM channels);"
+	},
+	Check {
+		description: "quickmarks without an item",
+		count: "SELECT COUNT(*) FROM quickmarks WHERE hash NOT IN (SELECT hash FROM items);",
+		repair: "DELETE FROM quickmarks WHERE hash NOT IN (SELECT hash FROM items);"
+	},
+	Check {
+		description: "items read in one feed but not in another",
+		count: "SELECT COUNT(*) FROM items
+			WHERE read=0 AND hash IN (SELECT hash FROM items WHERE read=1);",
+		repair: "UPDATE items SET read=1,
+			read_at = (SELECT MAX(read_at) FROM items AS copy WHERE copy.hash = items.hash)
+			WHERE read=0 AND hash IN (SELECT hash FROM items WHERE read=1);"
+	},
+	Check {
+		description: "items starred in one feed but not in another",
+		count: "SELECT COUNT(*) FROM items
+			WHERE starred_at IS NULL AND hash IN (SELECT hash FROM items WHERE starred_at IS NOT NULL);",
+		repair: "UPDATE items
+			SET starred_at = (SELECT MAX(starred_at) FROM items AS copy WHERE copy.hash = items.hash)
+			WHERE starred_at IS NULL AND hash IN (SELECT hash FROM items WHERE starred_at IS NOT NULL);"
+	},
+	Check {
+		description: "items snoozed in one feed but not in another",
+		count: "SELECT COUNT(*) FROM items
+			WHERE snoozed_until IS NULL AND hash IN (SELECT hash FROM items WHERE snoozed_until IS NOT NULL);",
+		repair: "UPDATE items
+			SET snoozed_until = (SELECT MAX(snoozed_until) FROM items AS copy WHERE copy.hash = items.hash)
+			WHERE snoozed_until IS NULL AND hash IN (SELECT hash FROM items WHERE snoozed_until IS NOT NULL);"
+	},
+	Check {
+		description: "quickmarks for items already read",
+		count: "SELECT COUNT(*) FROM quickmarks WHERE hash IN (SELECT hash FROM items WHERE read=1);",
+		repair: "DELETE FROM quickmarks WHERE hash IN (SELECT hash FROM items WHERE read=1);"
+	},
+	Check {
+		description: "quickmarks for starred items",
+		count: "SELECT COUNT(*) FROM quickmarks WHERE hash IN (SELECT hash FROM items WHERE starred_at IS NOT NULL);",
+		repair: "DELETE FROM quickmarks WHERE hash IN (SELECT hash FROM items WHERE starred_at IS NOT NULL);"
+	},
+	Check {
+		description: "quickmarks for snoozed items",
+		count: "SELECT COUNT(*) FROM quickmarks WHERE hash IN (
+			SELECT hash FROM items WHERE snoozed_until > CAST(strftime('%s', 'now') AS INTEGER)
+		);",
+		repair: "DELETE FROM quickmarks WHERE hash IN (
+			SELECT hash FROM items WHERE snoozed_until > CAST(strftime('%s', 'now') AS INTEGER)
+		);"
+	},
+	Check {
+		description: "unread items without a quickmark",
+		count: "SELECT COUNT(DISTINCT hash) FROM items
+			WHERE read=0 AND starred_at IS NULL AND hash NOT IN (SELECT hash FROM quickmarks)
+				AND (snoozed_until IS NULL OR snoozed_until <= CAST(strftime('%s', 'now') AS INTEGER));",
+		repair: ADD_MISSING_QUICKMARKS
+	}
+];
+
+///Errors about the version of the database schema
+#[derive(Debug)]
+pub enum SchemaError {
+	///The database was written by a newer corkboard (found version, newest we know)
+	TooNew(usize, usize)
+}
+impl fmt::Display for SchemaError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			SchemaError::TooNew(found, known) => write!(f,
+				"The database is at schema version {found}, but this corkboard only knows up to version {known}. \
+				It was written by a newer corkboard, please update.")
+		}
+	}
+}
+impl error::Error for SchemaError {}
+
+///Add the untitled column to the items of databases from before it existed,
+///that are also from before versioning.
+///
+///Their items can't be told apart, they keep showing their whole text.
+fn add_untitled_column(db: &Connection) -> Result<()> {
+	let has_column: bool = db.query_row(
+		"SELECT COUNT(*) > 0 FROM pragma_table_info('items') WHERE name = 'untitled';", [], |row| row.get(0)
+	)?;
+	if !has_column {
+		db.execute("ALTER TABLE items ADD COLUMN untitled BOOLEAN NOT NULL DEFAULT 0;", [])?;
+	}
+	Ok(())
+}
+
+///How long we wait for another connection to release the database before failing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+///Errors about running alongside other corkboard processes
+#[derive(Debug)]
+pub enum LockError {
+	///Another update holds the lock (at this path)
+	UpdateRunning(PathBuf),
+	///Other processes have the database open (they hold a lock at this path)
+	InUse(PathBuf)
+}
+impl fmt::Display for LockError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			LockError::UpdateRunning(path) => write!(f,
+				"Another corkboard update is already running (it holds {path:?}), try again once it's done"),
+			LockError::InUse(path) => write!(f,
+				"Other corkboards are using the database (they hold {path:?}), try again once they're done")
+		}
+	}
+}
+impl error::Error for LockError {}
+
+///Errors about queries run with [Database::query]
+#[derive(Debug)]
+pub enum QueryError {
+	///The query would change the database
+	NotReadOnly
+}
+impl fmt::Display for QueryError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			QueryError::NotReadOnly => write!(f, "Only queries that read from the database are allowed")
+		}
+	}
+}
+impl error::Error for QueryError {}
+
+///What a query returned: the names of its columns, and every row in order
+#[derive(Debug)]
+pub struct QueryRows {
+	pub columns: Vec<String>,
+	pub rows: Vec<Vec<Value>>
+}
+
+///How many snapshots are kept, the oldest ones are deleted past this
+pub const SNAPSHOTS_KEPT: usize = 10;
+
+///Errors about backups and restoring them
+#[derive(Debug)]
+pub enum BackupError {
+	///The file doesn't exist, or isn't an SQLite database
+	NotADatabase(PathBuf),
+	///The file is an SQLite database but not a corkboard one (missing this table)
+	NotCorkboard(PathBuf, &'static str),
+	///SQLite found the file damaged (what it found)
+	Damaged(PathBuf, String)
+}
+impl fmt::Display for BackupError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			BackupError::NotADatabase(path) => write!(f, "{path:?} is not an SQLite database"),
+			BackupError::NotCorkboard(path, table) => write!(f,
+				"{path:?} is not a corkboard database (it has no {table} table)"),
+			BackupError::Damaged(path, problem) => write!(f, "{path:?} is damaged: {problem}")
+		}
+	}
+}
+impl error::Error for BackupError {}
+
+///Copy every page of _source_ into _destination_ with the backup API.
+///
+///All the pages go in a single step, so the copy is one read transaction on _source_
+///(copying a few pages at a time starts over whenever someone else writes).
+///If either database is locked we retry for up to [BUSY_TIMEOUT].
+fn copy_database(source: &Connection, destination: &mut Connection) -> Result<()> {
+	let backup = Backup::new(source, destination)?;
+	let started = Instant::now();
+	loop {
+		match backup.step(-1)? {
+			StepResult::Done => return Ok(()),
+			_ if started.elapsed() > BUSY_TIMEOUT => return Err(anyhow!("The database stayed locked")),
+			_ => thread::sleep(Duration::from_millis(100))
+		}
+	}
+}
+
+///Variables with the key of an encrypted database: the passphrase, or a file holding it
+pub const KEY_VARIABLES: (&str, &str) = ("CORKDB_KEY", "CORKDB_KEY_FILE");
+///Variables with the new key for [Database::change_key] (when the database already has a key)
+pub const NEW_KEY_VARIABLES: (&str, &str) = ("CORKDB_NEW_KEY", "CORKDB_NEW_KEY_FILE");
+
+///Errors about encrypted databases and their keys
+#[derive(Debug)]
+pub enum EncryptionError {
+	///A key was given but corkboard was built without the `encryption` feature
+	NotSupported,
+	///Both variables for a key are set (the passphrase one, the file one)
+	BothKeys(&'static str, &'static str),
+	///A key is needed and none of these variables is set
+	NoKey(&'static str, &'static str),
+	///The key (or the file holding it) is empty
+	EmptyKey,
+	///The database can't be read with the key given
+	WrongKey,
+	///The database can't be read without a key
+	NeedsKey
+}
+impl fmt::Display for EncryptionError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			EncryptionError::NotSupported => write!(f,
+				"This corkboard was built without encryption support (the \"encryption\" feature)"),
+			EncryptionError::BothKeys(key, file) => write!(f, "Set either ${key} or ${file}, not both"),
+			EncryptionError::NoKey(key, file) => write!(f, "No key given, set ${key} or ${file}"),
+			EncryptionError::EmptyKey => write!(f, "The key is empty"),
+			EncryptionError::WrongKey => write!(f,
+				"Could not read the database with the key given, the key is wrong or the database is not encrypted"),
+			EncryptionError::NeedsKey => write!(f,
+				"Could not read the database, it is encrypted (set ${} or ${}) or it's not a database",
+				KEY_VARIABLES.0, KEY_VARIABLES.1)
+		}
+	}
+}
+impl error::Error for EncryptionError {}
+
+///Passphrase of an encrypted database.
+#[derive(Clone)]
+pub struct DatabaseKey(String);
+impl DatabaseKey {
+	///A key from its passphrase, which can't be empty
+	pub fn new(passphrase: &str) -> Result<Self, EncryptionError> {
+		if passphrase.is_empty() {
+			return Err(EncryptionError::EmptyKey);
+		}
+		Ok(Self(passphrase.to_string()))
+	}
+
+	///The key in the [KEY_VARIABLES], None if neither is set.
+	pub fn from_env() -> Result<Option<Self>> {
+		Self::from_variables(KEY_VARIABLES)
+	}
+
+	///The key in a pair of variables (the passphrase, a file holding it), None if neither is set.
+	///
+	///A trailing newline in the file is not part of the key.
+	pub fn from_variables((key_variable, file_variable): (&'static str, &'static str)) -> Result<Option<Self>> {
+		let key = match (env::var(key_variable), env::var(file_variable)) {
+			(Err(_), Err(_)) => return Ok(None),
+			(Ok(_), Ok(_)) => return Err(EncryptionError::BothKeys(key_variable, file_variable).into()),
+			(Ok(passphrase), Err(_)) => Self::new(&passphrase)?,
+			(Err(_), Ok(file)) => {
+				let contents = fs::read_to_string(&file)
+					.with_context(|| format!("Could not read the key file {file:?}"))?;
+				Self::new(contents.trim_end_matches(['\n', '\r']))?
+			}
+		};
+		if cfg!(not(feature = "encryption")) {
+			return Err(EncryptionError::NotSupported.into());
+		}
+		Ok(Some(key))
+	}
+}
+impl fmt::Debug for DatabaseKey {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		//never printed, it would end up in logs
+		write!(f, "DatabaseKey(..)")
+	}
+}
+
+///_path_ with _suffix_ added to the file name (`corkdb` and `-wal` make `corkdb-wal`)
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+	let mut with_suffix = path.as_os_str().to_owned();
+	with_suffix.push(suffix);
+	PathBuf::from(with_suffix)
+}
+
+///Export the database open in _db_ to a new file at _destination_, encrypted with _key_.
+#[cfg(feature = "encryption")]
+fn export_with_key(db: &Connection, destination: &Path, key: &DatabaseKey) -> Result<()> {
+	if destination.exists() {
+		fs::remove_file(destination)?;
+	}
+
+	let version: usize = db.query_row("PRAGMA user_version;", [], |row| row.get(0))?;
+	db.execute("ATTACH DATABASE ?1 AS rekeyed KEY ?2;", params![destination.to_string_lossy(), key.0])?;
+	let export = db.query_row("SELECT sqlcipher_export('rekeyed');", [], |_| Ok(()))
+		//not copied by the export
+		.and_then(|_| db.pragma_update(Some(rusqlite::DatabaseName::Attached("rekeyed")), "user_version", version));
+	db.execute("DETACH DATABASE rekeyed;", [])?;
+	export.context("Could not export the database under the new key")
+}
+
+///Move the database file at _from_ over the one at _to_, whose -wal and -shm files are deleted
+///first (they belong to the database being replaced). No connection to _to_ may be open.
+#[cfg(feature = "encryption")]
+fn replace_database_file(from: &Path, to: &Path) -> Result<()> {
+	for suffix in ["-wal", "-shm"] {
+		let file = with_suffix(to, suffix);
+		if file.exists() {
+			fs::remove_file(&file)?;
+		}
+	}
+	fs::rename(from, to)
+		.with_context(|| format!("Could not move {from:?} to {to:?}"))
+}
+
+///Open a connection to the database at _path_, unlocking it with _key_ if there is one.
+///
+///Fails if the database can't be read, a wrong key only shows up on the first read.
+fn connect(path: &Path, flags: OpenFlags, key: Option<&DatabaseKey>) -> Result<Connection> {
+	let db = Connection::open_with_flags(path, flags)?;
+	if let Some(key) = key {
+		db.pragma_update(None, "key", &key.0)?;
+	}
+	match db.query_row("SELECT COUNT(*) FROM sqlite_master;", [], |_| Ok(())) {
+		Ok(()) => Ok(db),
+		Err(e) if e.sqlite_error_code() == Some(rusqlite::ErrorCode::NotADatabase) => match key {
+			Some(_) => Err(EncryptionError::WrongKey.into()),
+			None => Err(EncryptionError::NeedsKey.into())
+		},
+		Err(e) => Err(e.into())
+	}
+}
+
+///Flags for the connections that only read
+const READ_ONLY: OpenFlags = OpenFlags::SQLITE_OPEN_READ_ONLY
+	.union(OpenFlags::SQLITE_OPEN_URI)
+	.union(OpenFlags::SQLITE_OPEN_NO_MUTEX);
+
+///Held while an update runs, no other update can start until it's dropped
+pub struct UpdateLock {
+	_file: File
+}
+
+///What happened to the snapshots when the key of the database changed
+#[derive(Debug, Default)]
+pub struct RekeyedSnapshots {
+	///Snapshots now encrypted with the new key
+	pub reencrypted: usize,
+	///Snapshots that could not be opened with the current key (or none) and were deleted
+	pub deleted: usize
+}
+
+///Open the file next to _db_path_ that every connection holds a shared lock on (see Concurrency),
+///waiting if the key is being changed.
+fn lock_in_use(db_path: &Path) -> Result<File> {
+	let lock_path = with_suffix(db_path, ".in-use");
+	let file = OpenOptions::new().create(true).truncate(false).write(true).open(&lock_path)
+		.with_context(|| format!("Could not open the lock at {lock_path:?}"))?;
+	FileExt::lock_shared(&file)
+		.with_context(|| format!("Could not take the lock at {lock_path:?}"))?;
+	Ok(file)
+}
+
 ///Encapsulates a connection to the sqlite db
 pub struct Database {
 	///The rusqlite connection to the database
-	db: Connection
+	db: Connection,
+	///Where the database file is, other files (backups, the update lock) go next to it
+	path: PathBuf,
+	///Key of the database, if it's encrypted. Every other connection we open uses it too.
+	key: Option<DatabaseKey>,
+	///Shared lock held for as long as the database is open, see [lock_in_use]
+	#[cfg_attr(not(feature = "encryption"), allow(dead_code))]
+	in_use: File
 }
 impl Database {
-	///Initialize or connect to a sqlite database
+	///Initialize or connect to a sqlite database, bringing its schema up to date
 	pub fn setup<P: AsRef<Path>>(db_path: P) -> Result<Self> {
-		let db = Connection::open(db_path)?;
-
-		//Channel entries
-		db.execute(
-			"CREATE TABLE IF NOT EXISTS channels (
-				id INTEGER PRIMARY KEY AUTOINCREMENT,
-				title VARCHAR(256),
-				link VARCHAR(256) UNIQUE,
-				description TEXT,
-				last_build_date VARCHAR
-			);", [])?;
-
-		//Item entries
-		db.execute(
-			"CREATE TABLE IF NOT EXISTS items (
-				hash VARCHAR PRIMARY KEY,
-				title_or_desc VARCHAR(256) NOT NULL,
-				url VARCHAR(256),
-				pub_date VARCHAR,
-				read BOOLEAN NOT NULL,
-				channel INTEGER NOT NULL,
-				FOREIGN KEY(channel) REFERENCES channels(id) ON DELETE CASCADE
-			);", [])?;
-
-		//Quickmarks
-		db.execute(
-			"CREATE TABLE IF NOT EXISTS quickmarks (
-				position INTEGER,
-				hash VARCHAR,
-				FOREIGN KEY(hash) REFERENCES items(hash) ON DELETE CASCADE
-			);" ,[])?;
-
-		Ok(Self {db})
+		Self::setup_with_key(db_path, None)
+	}
+
+	///Like [Database::setup], for an encrypted database (or one to be created encrypted) when _key_ is given.
+	pub fn setup_with_key<P: AsRef<Path>>(db_path: P, key: Option<DatabaseKey>) -> Result<Self> {
+		let in_use = lock_in_use(db_path.as_ref())?;
+		let db = connect(db_path.as_ref(), OpenFlags::default(), key.as_ref())?;
+		let database = Self {db, path: db_path.as_ref().to_path_buf(), key, in_use};
+
+		//Wait for other connections instead of failing with "database is locked"
+		database.db.busy_timeout(BUSY_TIMEOUT)?;
+		//Readers don't block the writer (nor the other way around), the mode is kept in the file
+		let journal_mode: String = database.db.query_row("PRAGMA journal_mode=WAL;", [], |row| row.get(0))?;
+		if !journal_mode.eq_ignore_ascii_case("wal") {
+			return Err(anyhow!("Could not switch the database to WAL mode (it's in {journal_mode} mode)"));
+		}
+
+		database.upgrade()?;
+		Ok(database)
+	}
+
+	///Bring the schema up to date, then enforce foreign keys.
+	fn upgrade(&self) -> Result<()> {
+		//Migrations rebuild tables, which can't be done with foreign keys enforced.
+		//The setting can't be changed inside a transaction, so it's done around all of them.
+		self.db.pragma_update(None, "foreign_keys", false)?;
+		self.migrate()
+			.context("Could not update the database schema")?;
+
+		//Off by default in SQLite (unless built otherwise)
+		self.db.pragma_update(None, "foreign_keys", true)?;
+		Ok(())
+	}
+
+	///Run _f_ inside a transaction, it's commited if _f_ succeeds and rolled back if it fails.
+	///
+	///Used to make all the writes of a command atomic, the methods called by _f_ should not
+	///open transactions of their own.
+	pub fn transaction<T, F: FnOnce() -> Result<T>>(&self, f: F) -> Result<T> {
+		let transaction = self.write_transaction()?;
+		let result = f()?;
+		transaction.commit()?;
+		Ok(result)
+	}
+
+	///A transaction that takes the write lock right away.
+	///
+	///SQLite's default (deferred) transactions start reading and only ask for the write lock
+	///on their first write, if another connection wrote in between they fail at once
+	///instead of waiting for the busy timeout.
+	fn write_transaction(&self) -> Result<Transaction<'_>> {
+		Ok(Transaction::new_unchecked(&self.db, TransactionBehavior::Immediate)?)
+	}
+
+	///Take the update lock, so that no other __up__ runs at the same time.
+	///
+	///The lock is advisory (a lock on a file next to the database) and is released when
+	///the returned value is dropped, or if the process dies.
+	pub fn lock_updates(&self) -> Result<UpdateLock> {
+		let lock_path = with_suffix(&self.path, ".lock");
+		let file = OpenOptions::new().create(true).truncate(false).write(true).open(&lock_path)
+			.with_context(|| format!("Could not open the update lock at {lock_path:?}"))?;
+		match file.try_lock_exclusive() {
+			Ok(true) => Ok(UpdateLock {_file: file}),
+			Ok(false) => Err(LockError::UpdateRunning(lock_path).into()),
+			Err(e) => Err(e)
+				.with_context(|| format!("Could not take the update lock at {lock_path:?}"))
+		}
+	}
+
+	///Schema version of the database, the number of migrations applied to it.
+	pub fn schema_version(&self) -> Result<usize> {
+		let version: usize = self.db.query_row("PRAGMA user_version;", [], |row| row.get(0))?;
+		Ok(version)
+	}
+
+	///Apply all the migrations that the database is missing.
+	///
+	///If the database already holds something a snapshot is taken first,
+	///a failed migration rolls back, leaving the database at the last good version.
+	fn migrate(&self) -> Result<()> {
+		let version = self.schema_version()?;
+		if version > MIGRATIONS.len() {
+			return Err(SchemaError::TooNew(version, MIGRATIONS.len()).into());
+		}
+		if version == MIGRATIONS.len() {
+			return Ok(());
+		}
+
+		let has_tables: bool = self.db.query_row(
+			"SELECT COUNT(*) > 0 FROM sqlite_master;", [], |row| row.get(0)
+		)?;
+		if has_tables {
+			self.snapshot(&format!("v{version}"))
+				.context("Could not take a snapshot of the database before migrating")?;
+			if version == 0 {
+				add_untitled_column(&self.db)?;
+			}
+		}
+
+		for (applied, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+			let transaction = self.write_transaction()?;
+			//another corkboard starting at the same time may have applied it while we waited
+			if self.schema_version()? > applied {
+				continue;
+			}
+			transaction.execute_batch(migration)
+				.with_context(|| format!("Migration to schema version {} failed", applied + 1))?;
+			transaction.pragma_update(None, "user_version", applied + 1)?;
+			transaction.commit()?;
+		}
+
+		Ok(())
+	}
+
+	///Run a single query that only reads, meant for the views (see Views in the module docs).
+	///
+	///It runs on its own read only connection, so nothing it does can change the database.
+	pub fn query(&self, sql: &str) -> Result<QueryRows> {
+		let db = connect(&self.path, READ_ONLY, self.key.as_ref())?;
+		db.busy_timeout(BUSY_TIMEOUT)?;
+		//a read only connection can still write temporary tables
+		db.pragma_update(None, "query_only", true)?;
+
+		let read_only = |e: rusqlite::Error| -> anyhow::Error {
+			match e.sqlite_error_code() {
+				Some(rusqlite::ErrorCode::ReadOnly) => QueryError::NotReadOnly.into(),
+				_ => e.into()
+			}
+		};
+		let mut statement = db.prepare(sql).map_err(read_only)?;
+		let columns: Vec<String> = statement.column_names().into_iter().map(String::from).collect();
+		let rows = statement.query_map([], |row| {
+			(0..columns.len()).map(|i| row.get::<_, Value>(i)).collect()
+		}).map_err(read_only)?;
+		let rows = rows.collect::<rusqlite::Result<_>>().map_err(read_only)?;
+
+		Ok(QueryRows { columns, rows })
+	}
+
+	///Copy the database to _path_ (replacing whatever is there), safe while others write to it.
+	///
+	///The copy is written next to _path_ and then moved over it, a failed backup leaves
+	///an older file at _path_ as it was.
+	pub fn backup(&self, path: &Path) -> Result<()> {
+		let partial = with_suffix(path, ".partial");
+		if partial.exists() {
+			fs::remove_file(&partial)?;
+		}
+
+		{
+			//an encrypted database is copied with the same key, SQLCipher can't copy between keys
+			let mut destination = connect(&partial, OpenFlags::default(), self.key.as_ref())
+				.with_context(|| format!("Could not create {partial:?}"))?;
+			copy_database(&self.db, &mut destination)
+				.with_context(|| format!("Could not copy the database to {partial:?}"))?;
+		}
+		fs::rename(&partial, path)
+			.with_context(|| format!("Could not move {partial:?} to {path:?}"))?;
+		Ok(())
+	}
+
+	///Directory the snapshots are kept in, `<database>.snapshots`
+	pub fn snapshots_dir(&self) -> PathBuf {
+		with_suffix(&self.path, ".snapshots")
+	}
+
+	///All the snapshots taken, oldest first.
+	pub fn snapshots(&self) -> Result<Vec<PathBuf>> {
+		let dir = self.snapshots_dir();
+		if !dir.exists() {
+			return Ok(Vec::new());
+		}
+		let mut snapshots: Vec<PathBuf> = fs::read_dir(&dir)
+			.with_context(|| format!("Could not read {dir:?}"))?
+			.filter_map(|entry| entry.ok().map(|e| e.path()))
+			.filter(|path| path.extension().is_some_and(|e| e == "corkdb"))
+			.collect();
+		//names start with the time they were taken
+		snapshots.sort();
+		Ok(snapshots)
+	}
+
+	///Take a snapshot, named after the time and _reason_, then delete the oldest
+	///ones past [SNAPSHOTS_KEPT]. Returns where it was written.
+	pub fn snapshot(&self, reason: &str) -> Result<PathBuf> {
+		let path = self.write_snapshot(reason)?;
+		self.rotate_snapshots()?;
+		Ok(path)
+	}
+
+	///Take a snapshot without deleting old ones
+	fn write_snapshot(&self, reason: &str) -> Result<PathBuf> {
+		let dir = self.snapshots_dir();
+		fs::create_dir_all(&dir)
+			.with_context(|| format!("Could not create {dir:?}"))?;
+		let path = dir.join(format!("{}-{reason}.corkdb", Utc::now().format("%Y%m%dT%H%M%S%.3fZ")));
+		self.backup(&path)?;
+		Ok(path)
+	}
+
+	///Delete the oldest snapshots, keeping [SNAPSHOTS_KEPT]
+	fn rotate_snapshots(&self) -> Result<()> {
+		let snapshots = self.snapshots()?;
+		let extra = snapshots.len().saturating_sub(SNAPSHOTS_KEPT);
+		for old in &snapshots[..extra] {
+			fs::remove_file(old)
+				.with_context(|| format!("Could not delete the old snapshot {old:?}"))?;
+		}
+		Ok(())
+	}
+
+	///Check that the file at _path_ is a corkboard database we can use, returns its schema version.
+	///
+	///Older versions are fine, they are migrated once restored.
+	///The backup of an encrypted database must have the same key.
+	pub fn check_backup(&self, path: &Path) -> Result<usize> {
+		if !path.is_file() {
+			return Err(BackupError::NotADatabase(path.to_path_buf()).into());
+		}
+		let db = match connect(path, READ_ONLY, self.key.as_ref()) {
+			Ok(db) => db,
+			Err(e) if e.is::<EncryptionError>() && self.key.is_some() => return Err(e),
+			Err(_) => return Err(BackupError::NotADatabase(path.to_path_buf()).into())
+		};
+		let version: usize = db.query_row("PRAGMA user_version;", [], |row| row.get(0))
+			.map_err(|_| BackupError::NotADatabase(path.to_path_buf()))?;
+		if version > MIGRATIONS.len() {
+			return Err(SchemaError::TooNew(version, MIGRATIONS.len()).into());
+		}
+
+		let problem: String = db.query_row("PRAGMA quick_check(1);", [], |row| row.get(0))?;
+		if problem != "ok" {
+			return Err(BackupError::Damaged(path.to_path_buf(), problem).into());
+		}
+		for table in ["channels", "items", "quickmarks"] {
+			let exists: bool = db.query_row(
+				"SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?;",
+				[table],
+				|row| row.get(0)
+			)?;
+			if !exists {
+				return Err(BackupError::NotCorkboard(path.to_path_buf(), table).into());
+			}
+		}
+		Ok(version)
+	}
+
+	///Replace the database with the backup at _path_, after checking it (see [Database::check_backup])
+	///and taking a snapshot of the current one. Returns where the snapshot is.
+	///
+	///Other connections see the restored database once this returns, a restored older
+	///version is migrated right away.
+	pub fn restore(&self, path: &Path) -> Result<PathBuf> {
+		self.check_backup(path)?;
+		//no rotation yet, the backup being restored may be the oldest snapshot
+		let snapshot = self.write_snapshot("restore")
+			.context("Could not take a snapshot of the database before restoring")?;
+
+		{
+			let source = connect(path, READ_ONLY, self.key.as_ref())?;
+			let mut destination = connect(&self.path, OpenFlags::default(), self.key.as_ref())?;
+			destination.busy_timeout(BUSY_TIMEOUT)?;
+			copy_database(&source, &mut destination)
+				.with_context(|| format!("Could not restore {path:?}"))?;
+		}
+
+		self.upgrade()?;
+		self.rotate_snapshots()?;
+		Ok(snapshot)
+	}
+
+	///Encrypt the database with _new_key_, or change its key if it already has one.
+	///
+	///The database is exported to a new file under the new key, which then replaces it.
+	///The snapshots get the new key too, those that can't be opened with the current one
+	///are deleted (backups elsewhere keep the old key, or stay unencrypted).
+	///Fails if another corkboard is using the database, it would keep writing to the old file.
+	#[cfg(feature = "encryption")]
+	pub fn change_key(self, new_key: &DatabaseKey) -> Result<RekeyedSnapshots> {
+		let _lock = self.lock_updates()?;
+		//turns our shared lock into an exclusive one, processes starting meanwhile wait for us
+		match self.in_use.try_lock_exclusive() {
+			Ok(true) => {},
+			Ok(false) => return Err(LockError::InUse(with_suffix(&self.path, ".in-use")).into()),
+			Err(e) => return Err(e).context("Could not lock the database")
+		}
+
+		let exported = with_suffix(&self.path, ".rekeying");
+		export_with_key(&self.db, &exported, new_key)?;
+		let snapshots = self.snapshots()?;
+
+		//the export read everything through our connection, the -wal files go with the old database
+		let Database {db, path, key, in_use} = self;
+		db.close().map_err(|(_, e)| e)?;
+		replace_database_file(&exported, &path)?;
+
+		let mut rekeyed = RekeyedSnapshots::default();
+		for snapshot in snapshots {
+			let exported = with_suffix(&snapshot, ".rekeying");
+			let export = connect(&snapshot, OpenFlags::default(), key.as_ref())
+				.and_then(|db| {
+					export_with_key(&db, &exported, new_key)?;
+					db.close().map_err(|(_, e)| e)?;
+					Ok(())
+				});
+			match export {
+				Ok(()) => {
+					replace_database_file(&exported, &snapshot)?;
+					rekeyed.reencrypted += 1;
+				},
+				//under an older key (or none when we have one), keeping it would defeat the new key
+				Err(_) => {
+					for file in [&exported, &snapshot, &with_suffix(&snapshot, "-wal"), &with_suffix(&snapshot, "-shm")] {
+						if file.exists() {
+							fs::remove_file(file)
+								.with_context(|| format!("Could not delete {file:?}"))?;
+						}
+					}
+					rekeyed.deleted += 1;
+				}
+			}
+		}
+		drop(in_use);
+		Ok(rekeyed)
+	}
+
+	///Encrypt the database with _new_key_, needs the `encryption` feature.
+	#[cfg(not(feature = "encryption"))]
+	pub fn change_key(self, _new_key: &DatabaseKey) -> Result<RekeyedSnapshots> {
+		Err(EncryptionError::NotSupported.into())
 	}
 
 	///Get all the channels from the database (without their respective items)
@@ -100,7 +1751,7 @@ impl Database {
 				title: row.get(0)?,
 				link: row.get(1)?,
 				description: row.get(2)?,
-				last_build_date: row.get(3)?,
+				last_build_date: from_timestamp(row.get(3)?),
 				items: Vec::new()
 			})
 		})?;
@@ -108,38 +1759,6 @@ impl Database {
 		Ok(channels.flatten().collect())
 	}
 
-	///Get all the channels from the database (with their items)
-	pub fn all_channels_with_items(&self) -> Result<Vec<Channel>> {
-		let mut channels = self.all_channels()?;
-
-		for c in channels.iter_mut() {
-			c.items = self.get_items(&c)?;
-		}
-
-		Ok(channels)
-	}
-
-	///Returns the items in the database that belong to a channel.
-	pub fn get_items(&self, channel: &Channel) -> Result<Vec<Item>> {
-		let mut statement = self.db.prepare(
-			"SELECT hash, title_or_desc, url, pub_date, read
-			FROM items LEFT JOIN channels ON items.channel == channels.id
-			WHERE link = (?);"
-		)?;
-
-		let items = statement.query_map([&channel.link], |row| {
-			Ok(Item {
-				title_or_description_hash: row.get(0)?,
-				title_or_description: row.get(1)?,
-				link: row.get(2)?,
-				pub_date: row.get(3)?,
-				read: row.get(4)?
-			})
-		})?;
-
-		Ok(items.flatten().collect())
-	}
-
 	///Add a new channel into db
 	pub fn add_channel(&self, channel: &Channel) -> Result<()> {
 		let mut statement = self.db.prepare(
@@ -151,72 +1770,93 @@ impl Database {
 			channel.title,
 			channel.link,
 			channel.description,
-			channel.last_build_date
+			timestamp(channel.last_build_date)
 		])?;
 
 		Ok(())
 	}
 
 	///Adds new items to the database, associates them with the channel passed.
-	///Note that if the items have the same hash as another in the database
-	///then the insertion is ignored.
-	pub fn add_items(&self, channel: &Channel, items: &[Item]) -> Result<()> {
+	///Items the channel already has (or had, before they were pruned) are ignored,
+	///so the whole feed may be passed.
+	///
+	///An item already in another channel keeps its read (starred and snoozed) state in this one.
+	///Returns the items that were actually inserted.
+	pub fn add_items(&self, channel: &Channel, items: &[Item]) -> Result<Vec<Item>> {
 		let channel_id:u64 = self.db.prepare("SELECT id FROM channels WHERE link = (?);")?
 			.query_row([&channel.link], |row| {row.get(0)})?;
 
 		let mut statement = self.db.prepare(
-			"INSERT
-			INTO items (hash, title_or_desc, url, pub_date, read, channel)
-			VALUES (?, ?, ?, ?, ?, ?);"
+			"INSERT OR IGNORE
+			INTO items (hash, title_or_desc, url, pub_date, read, channel, description, content, first_seen, read_at,
+				starred_at, snoozed_until, queued_at, auto_read, untitled)
+			SELECT ?1, ?2, ?3, ?4, COALESCE((SELECT MAX(read) FROM items WHERE hash = ?1), ?5), ?6, ?7, ?8,
+				COALESCE((SELECT MIN(first_seen) FROM items WHERE hash = ?1), ?9),
+				(SELECT MAX(read_at) FROM items WHERE hash = ?1),
+				(SELECT MAX(starred_at) FROM items WHERE hash = ?1),
+				(SELECT MAX(snoozed_until) FROM items WHERE hash = ?1),
+				(SELECT MAX(queued_at) FROM items WHERE hash = ?1),
+				COALESCE((SELECT MAX(auto_read) FROM items WHERE hash = ?1), 0), ?10
+			WHERE NOT EXISTS (SELECT 1 FROM tombstones WHERE channel = ?6 AND hash = ?1);"
 		)?;
+		let now = Utc::now().timestamp();
 
+		let mut inserted = Vec::new();
 		for i in items {
-			statement.execute(rusqlite::params![
+			let rows_inserted = statement.execute(rusqlite::params![
 				i.title_or_description_hash,
 				i.title_or_description,
 				i.link,
-				i.pub_date,
+				timestamp(i.pub_date),
 				i.read,
-				channel_id
+				channel_id,
+				i.description,
+				i.content,
+				now,
+				i.untitled
 			]).context(i.title_or_description_hash.clone())?;
+
+			if rows_inserted == 1 {
+				inserted.push(i.clone());
+			}
 		}
 
-		Ok(())
+		Ok(inserted)
 	}
 
-	///Return all the items from the database that have not been read.
+	///Return all the items in the unread queue (not read, starred nor snoozed).
+	///An item in many channels is returned once.
 	pub fn all_unmarked_items(&self) -> Result<Vec<Item>> {
-		let mut statement = self.db.prepare("SELECT hash, title_or_desc, url, pub_date, read
-						FROM items
-						WHERE read=0;"
-		)?;
+		let mut statement = self.db.prepare(&format!(
+			"SELECT {ITEM_COLUMNS}
+			FROM items
+			WHERE read=0 AND starred_at IS NULL
+				AND (snoozed_until IS NULL OR snoozed_until <= CAST(strftime('%s', 'now') AS INTEGER))
+			GROUP BY hash;"
+		))?;
 
-		let items = statement.query_map([], |row| {
-			Ok(Item {
-				title_or_description_hash: row.get(0)?,
-				title_or_description: row.get(1)?,
-				link: row.get(2)?,
-				pub_date: row.get(3)?,
-				read: row.get(4)?
-			})
-		})?;
+		let items = statement.query_map([], item_from_row)?;
 
 		Ok(items.flatten().collect())
 	}
 
-	///Mark item as read given it's hash
+	///Mark item as read given it's hash, in every channel that has it.
+	///Marking an item that was already read keeps the time it was first read, reading one ends its snooze.
+	///Either way the item is now marked by the user, not by expire_unread_days.
 	pub fn mark_as_read(&self, hash: &str, read_state:bool) -> Result<()> {
 		let mut statement = self.db.prepare(
 			"UPDATE items
-			SET read=(?)
-			WHERE hash=(?);"
+			SET read=(?1), read_at = CASE WHEN ?1 THEN COALESCE(read_at, ?3) ELSE NULL END,
+				snoozed_until = CASE WHEN ?1 THEN NULL ELSE snoozed_until END, auto_read = 0,
+				queued_at = CASE WHEN ?1 THEN queued_at ELSE ?3 END
+			WHERE hash=(?2);"
 		)?;
-		let rows_changed = statement.execute(params![isize::from(read_state),hash])?;
+		let rows_changed = statement.execute(params![isize::from(read_state), hash, Utc::now().timestamp()])?;
 
-		if rows_changed == 1 {
+		if rows_changed >= 1 {
 			Ok(())
 		} else {
-			Err(anyhow!("Expected to change a single row, {} rows changed.", rows_changed))
+			Err(anyhow!("Expected to change at least one row, no item has the hash {hash}."))
 		}
 	}
 
@@ -235,7 +1875,7 @@ impl Database {
 			&format!("https://{}", feed_url)
 		])?;
 
-		//NOTE: how does this work with cascading ? might be a source of bugs.
+		//Items and their quickmarks go with the channel (ON DELETE CASCADE)
 		if rows_deleted == 1 {
 			Ok(())
 		} else {
@@ -243,78 +1883,462 @@ impl Database {
 		}
 	}
 
+	///Find the items whose title, description or content match _query_, best matches first.
+	///
+	///_query_ uses the FTS5 syntax: words (all must be present), "phrases in quotes",
+	///prefix* searches and OR/NOT. Items in many channels are returned once,
+	///along with their quickmark if they have one.
+	///Items without a publishing date never pass a date filter.
+	pub fn search(&self, query: &str, filter: &ItemFilter) -> Result<Vec<(Item, Option<i32>)>> {
+		let mut statement = self.db.prepare(&format!(
+			"SELECT {ITEM_COLUMNS}, quickmarks.position
+			FROM (SELECT rowid, rank FROM items_search WHERE items_search MATCH ?1) AS found
+			INNER JOIN items ON items.id = found.rowid
+			INNER JOIN channels ON channels.id = items.channel
+			LEFT JOIN quickmarks ON quickmarks.hash = items.hash
+			WHERE (?2 IS NULL OR items.read = ?2)
+				AND (?3 IS NULL OR channels.link IN (?3, 'http://' || ?3, 'https://' || ?3))
+				AND (?4 IS NULL OR items.pub_date >= ?4)
+				AND (?5 IS NULL OR items.pub_date <= ?5)
+			GROUP BY items.hash
+			ORDER BY MIN(found.rank);"
+		))?;
+
+		let items = statement.query_map(
+			params![query, filter.read, filter.feed, timestamp(filter.since), timestamp(filter.until)],
+			|row| Ok( (item_from_row(row)?, row.get(AFTER_ITEM_COLUMNS)?) )
+		)?;
+
+		//unlike other queries a bad search is the user's doing, so it must not be skipped silently
+		items.collect::<rusqlite::Result<_>>()
+			.with_context(|| format!("Could not search for {query:?}, see the FTS5 query syntax"))
+	}
+
+	///Items read at some time in the filter's range, most recently read first.
+	///
+	///The date range of the filter applies to when the item was read, not to its publishing date.
+	///Items read before corkboard kept track of that are not part of the history.
+	pub fn history(&self, filter: &ItemFilter) -> Result<Vec<Item>> {
+		let mut statement = self.db.prepare(&format!(
+			"SELECT {ITEM_COLUMNS}
+			FROM items INNER JOIN channels ON channels.id = items.channel
+			WHERE items.read = 1 AND items.read_at IS NOT NULL
+				AND (?1 IS NULL OR channels.link IN (?1, 'http://' || ?1, 'https://' || ?1))
+				AND (?2 IS NULL OR items.read_at >= ?2)
+				AND (?3 IS NULL OR items.read_at <= ?3)
+			GROUP BY items.hash
+			ORDER BY MAX(items.read_at) DESC, MAX(items.id) DESC;"
+		))?;
+
+		let items = statement.query_map(
+			params![filter.feed, timestamp(filter.since), timestamp(filter.until)],
+			item_from_row
+		)?;
+		Ok(items.collect::<rusqlite::Result<_>>()?)
+	}
+
+	///Global value of a setting, None if it was never set.
+	pub fn setting(&self, setting: Setting) -> Result<Option<i64>> {
+		let value = self.db.query_row(
+			"SELECT value FROM settings WHERE key = ?;", [setting.name()], |row| row.get(0)
+		);
+		match value {
+			Ok(value) => Ok(Some(value)),
+			Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+			Err(e) => Err(e.into())
+		}
+	}
+
+	///Every value that feeds have set for themselves, as (feed url, setting, value).
+	pub fn feed_settings(&self) -> Result<Vec<(String, Setting, i64)>> {
+		let mut values = Vec::new();
+		for setting in Setting::ALL.iter().filter(|s| s.per_feed()) {
+			//the column name comes from Setting, not from the user
+			let mut statement = self.db.prepare(&format!(
+				"SELECT link, {0} FROM channels WHERE {0} IS NOT NULL ORDER BY link;", setting.name()
+			))?;
+			let rows = statement.query_map([], |row| Ok((row.get(0)?, *setting, row.get(1)?)))?;
+			values.extend(rows.collect::<rusqlite::Result<Vec<_>>>()?);
+		}
+		Ok(values)
+	}
+
+	///Change a setting, for the feed at _feed_url_ (with or without the protocol) or globally.
+	///A value of None unsets it, falling back to the global value (or to no limit).
+	pub fn set_setting(&self, setting: Setting, value: Option<i64>, feed_url: Option<&str>) -> Result<()> {
+		let Some(feed_url) = feed_url else {
+			match value {
+				//not INSERT OR REPLACE, the journal would see the new row but not the old one
+				Some(value) => self.db.execute(
+					"INSERT INTO settings (key, value) VALUES (?1, ?2)
+					ON CONFLICT(key) DO UPDATE SET value = excluded.value;",
+					params![setting.name(), value]
+				)?,
+				None => self.db.execute("DELETE FROM settings WHERE key = ?;", [setting.name()])?
+			};
+			return Ok(());
+		};
+
+		if !setting.per_feed() {
+			return Err(SettingError::NotPerFeed(setting.name()).into());
+		}
+		let rows_changed = self.db.execute(
+			&format!("UPDATE channels SET {} = ?1
+				WHERE link IN (?2, 'http://' || ?2, 'https://' || ?2);", setting.name()),
+			params![value, feed_url]
+		)?;
+		if rows_changed == 0 {
+			return Err(SettingError::UnknownFeed(feed_url.to_string()).into());
+		}
+		Ok(())
+	}
+
+	///Delete the read items that the retention settings don't allow us to keep.
+	///
+	///Every pruned item leaves a tombstone (its channel and hash) behind,
+	///so that it isn't added again while the feed still lists it.
+	///Unread items are never pruned. Returns how many items were pruned.
+	pub fn prune(&self) -> Result<usize> {
+		let prunable: Vec<i64> = self.db.prepare(PRUNABLE_ITEMS)?
+			.query_map([], |row| row.get(0))?
+			.collect::<rusqlite::Result<_>>()?;
+
+		let mut tombstone_st = self.db.prepare(
+			"INSERT OR IGNORE INTO tombstones (channel, hash) SELECT channel, hash FROM items WHERE id = ?;"
+		)?;
+		let mut delete_st = self.db.prepare("DELETE FROM items WHERE id = ?;")?;
+		for id in &prunable {
+			tombstone_st.execute([id])?;
+			delete_st.execute([id])?;
+		}
+
+		Ok(prunable.len())
+	}
+
+	//JOURNAL---
+
+	///Run _f_ in a transaction (see [Database::transaction]), recording everything it changes
+	///in the journal as _command_, so that it can be undone.
+	pub fn journaled<T, F: FnOnce() -> Result<T>>(&self, command: &str, f: F) -> Result<T> {
+		self.transaction(|| {
+			self.db.execute(
+				"INSERT INTO journal (command, done_at) VALUES (?, ?);",
+				params![command, Utc::now().timestamp()]
+			)?;
+			let result = f()?;
+			self.finish_journal()?;
+			Ok(result)
+		})
+	}
+
+	///Stop recording, drop the new entry if it changed nothing, and forget the operations
+	///older than keep_journal_days.
+	fn finish_journal(&self) -> Result<()> {
+		self.db.execute("UPDATE journal SET recording = 0 WHERE recording;", [])?;
+		self.db.execute(
+			"DELETE FROM journal WHERE id = (SELECT MAX(id) FROM journal)
+				AND NOT EXISTS (SELECT 1 FROM journal_changes WHERE operation = journal.id);",
+			[]
+		)?;
+
+		let days = self.setting(Setting::KeepJournalDays)?.unwrap_or(JOURNAL_DAYS_DEFAULT);
+		if days >= 0 {
+			self.db.execute(
+				"DELETE FROM journal WHERE done_at <= ?;",
+				[Utc::now().timestamp() - days * 86400]
+			)?;
+		}
+		Ok(())
+	}
+
+	///Every operation that can be undone, most recent first (the order __undo__ goes in).
+	pub fn journal(&self) -> Result<Vec<JournalEntry>> {
+		let mut statement = self.db.prepare(
+			"SELECT command, done_at, (SELECT COUNT(*) FROM journal_changes WHERE operation = journal.id)
+			FROM journal
+			WHERE NOT recording
+			ORDER BY id DESC;"
+		)?;
+		let entries = statement.query_map([], |row| Ok(JournalEntry {
+			command: row.get(0)?,
+			done_at: from_timestamp(row.get(1)?).unwrap_or_default(),
+			changes: row.get(2)?
+		}))?;
+		Ok(entries.collect::<rusqlite::Result<_>>()?)
+	}
+
+	///Revert the last _count_ operations in the journal, most recent first, and return them.
+	///
+	///Their changes are reverted newest to oldest, then the quickmarks follow:
+	///items no longer unread lose theirs, items back in the queue get one at the end.
+	///Fails (reverting nothing) if the journal doesn't have that many operations.
+	pub fn undo(&self, count: usize) -> Result<Vec<JournalEntry>> {
+		let mut entries = self.journal()?;
+		if entries.len() < count {
+			return Err(UndoError::TooFew(count, entries.len()).into());
+		}
+		entries.truncate(count);
+		let operations: Vec<i64> = self.db.prepare("SELECT id FROM journal ORDER BY id DESC LIMIT ?;")?
+			.query_map([count], |row| row.get(0))?
+			.collect::<rusqlite::Result<_>>()?;
+
+		//a restored row may point to one restored after it (an item to its channel),
+		//the foreign keys are checked once the transaction commits
+		self.db.pragma_update(None, "defer_foreign_keys", true)?;
+
+		let mut changes_st = self.db.prepare(
+			"SELECT target, action, data FROM journal_changes WHERE operation = ? ORDER BY id DESC;"
+		)?;
+		for operation in operations {
+			let changes: Vec<(String, String, String)> = changes_st
+				.query_map([operation], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+				.collect::<rusqlite::Result<_>>()?;
+			for (target, action, data) in changes {
+				let (_, _, statement) = UNDO_CHANGES.iter()
+					.find(|(t, a, _)| *t == target && *a == action)
+					.ok_or_else(|| UndoError::UnknownChange(target.clone(), action.clone()))?;
+				self.db.execute(statement, [&data])
+					.with_context(|| format!("Could not undo a {action} in {target}"))?;
+			}
+			self.db.execute("DELETE FROM journal WHERE id = ?;", [operation])?;
+		}
+
+		self.db.execute(
+			"DELETE FROM quickmarks WHERE hash NOT IN (
+				SELECT hash FROM items WHERE read = 0 AND starred_at IS NULL
+					AND (snoozed_until IS NULL OR snoozed_until <= CAST(strftime('%s', 'now') AS INTEGER))
+			);",
+			[]
+		)?;
+		self.db.execute(ADD_MISSING_QUICKMARKS, [])?;
+		Ok(entries)
+	}
+
+	///Mark as read the unread items that expire_unread_days doesn't allow to wait any longer
+	///(flagged as read automatically), returns how many there were.
+	pub fn expire_unread(&self) -> Result<usize> {
+		let expired: Vec<String> = self.db.prepare(EXPIRED_ITEMS)?
+			.query_map([], |row| row.get(0))?
+			.collect::<rusqlite::Result<_>>()?;
+
+		let mut expire_st = self.db.prepare(
+			"UPDATE items SET read = 1, read_at = COALESCE(read_at, ?2), snoozed_until = NULL, auto_read = 1
+			WHERE hash = ?1;"
+		)?;
+		let now = Utc::now().timestamp();
+		for hash in &expired {
+			expire_st.execute(params![hash, now])?;
+			self.remove_quickmark(hash)?;
+		}
+		Ok(expired.len())
+	}
+
+	///Hashes of the items _item_ points at, fails if there are none.
+	pub fn resolve(&self, item: &ItemRef) -> Result<Vec<String>> {
+		let hashes: Vec<String> = match item {
+			ItemRef::Quickmark(position) => self.db.prepare("SELECT hash FROM quickmarks WHERE position = ?;")?
+				.query_map([position], |row| row.get(0))?
+				.collect::<rusqlite::Result<_>>()?,
+			//positions count from 1, there's nothing at 0
+			ItemRef::HistoryPosition(position) => match position.checked_sub(1) {
+				Some(index) => self.history(&ItemFilter::default())?
+					.into_iter()
+					.nth(index)
+					.map(|item| item.title_or_description_hash)
+					.into_iter()
+					.collect(),
+				None => Vec::new()
+			},
+			ItemRef::Hash(hash) => self.db.prepare("SELECT DISTINCT hash FROM items WHERE hash = ?;")?
+				.query_map([hash], |row| row.get(0))?
+				.collect::<rusqlite::Result<_>>()?,
+			ItemRef::Url(url) => self.db.prepare(
+					"SELECT DISTINCT hash FROM items WHERE url IN (?1, 'http://' || ?1, 'https://' || ?1);"
+				)?
+				.query_map([url], |row| row.get(0))?
+				.collect::<rusqlite::Result<_>>()?
+		};
+		if hashes.is_empty() {
+			return Err(ItemRefError::NotFound(item.clone()).into());
+		}
+		Ok(hashes)
+	}
+
+	///Mark the item with this hash as unread, it goes back to the queue with a quickmark at the end
+	///(unless it's starred).
+	pub fn unmark(&self, hash: &str) -> Result<()> {
+		self.mark_as_read(hash, false)?;
+		self.db.execute(ADD_MISSING_QUICKMARKS, [])?;
+		Ok(())
+	}
+
+	///Star the item with this hash (in every channel), taking it out of the unread queue.
+	///Starring it again keeps the time it was first starred.
+	pub fn star(&self, hash: &str) -> Result<()> {
+		self.db.execute(
+			"UPDATE items SET starred_at = COALESCE(starred_at, ?2) WHERE hash = ?1;",
+			params![hash, Utc::now().timestamp()]
+		)?;
+		self.remove_quickmark(hash)
+	}
+
+	///Unstar the item with this hash, if it's unread it goes back to the queue (with a quickmark at the end).
+	pub fn unstar(&self, hash: &str) -> Result<()> {
+		self.db.execute(
+			"UPDATE items
+			SET queued_at = CASE WHEN starred_at IS NULL THEN queued_at ELSE ?2 END, starred_at = NULL
+			WHERE hash = ?1;",
+			params![hash, Utc::now().timestamp()]
+		)?;
+		self.db.execute(ADD_MISSING_QUICKMARKS, [])?;
+		Ok(())
+	}
+
+	///Snooze the item with this hash (in every channel) until _until_, taking it out of the unread queue.
+	///A read item is unread again, so it comes back once the snooze is over.
+	pub fn snooze(&self, hash: &str, until: DateTime<Utc>) -> Result<()> {
+		self.db.execute(
+			"UPDATE items SET snoozed_until = ?2, queued_at = ?2, read = 0, read_at = NULL, auto_read = 0
+			WHERE hash = ?1;",
+			params![hash, until.timestamp()]
+		)?;
+		self.remove_quickmark(hash)
+	}
+
+	///Every item still snoozed, the first to come back first.
+	pub fn snoozed(&self) -> Result<Vec<Item>> {
+		let mut statement = self.db.prepare(&format!(
+			"SELECT {ITEM_COLUMNS}
+			FROM items
+			WHERE read = 0 AND snoozed_until > CAST(strftime('%s', 'now') AS INTEGER)
+			GROUP BY hash
+			ORDER BY MAX(snoozed_until), MIN(id);"
+		))?;
+		let items = statement.query_map([], item_from_row)?;
+		Ok(items.collect::<rusqlite::Result<_>>()?)
+	}
+
+	///Every starred item (read or not), most recently starred first.
+	pub fn starred(&self) -> Result<Vec<Item>> {
+		let mut statement = self.db.prepare(&format!(
+			"SELECT {ITEM_COLUMNS}
+			FROM items
+			WHERE starred_at IS NOT NULL
+			GROUP BY hash
+			ORDER BY MAX(starred_at) DESC, MIN(id);"
+		))?;
+		let items = statement.query_map([], item_from_row)?;
+		Ok(items.collect::<rusqlite::Result<_>>()?)
+	}
+
+	///Look for (and unless _dry_run_, repair) inconsistent data.
+	///
+	///Returns every check made with the number of problems found by it.
+	///Repairs happen in a single transaction. Fails without repairing anything if
+	///SQLite's own integrity check finds the file itself is damaged.
+	pub fn doctor(&self, dry_run: bool) -> Result<Vec<(&'static str, usize)>> {
+		let integrity: Vec<String> = self.db.prepare("PRAGMA integrity_check;")?
+			.query_map([], |row| row.get(0))?
+			.collect::<Result<_, _>>()?;
+		if integrity != ["ok"] {
+			return Err(anyhow!("The database file is damaged:\n{}", integrity.join("\n")));
+		}
+
+		let transaction = self.write_transaction()?;
+		let mut findings = Vec::new();
+
+		for check in CHECKS {
+			let found: usize = transaction.query_row(check.count, [], |row| row.get(0))?;
+			//Repairs can depend on earlier ones, so they have to run even on a dry run,
+			//the transaction is just never commited.
+			if found > 0 {
+				transaction.execute(check.repair, [])
+					.with_context(|| format!("Could not repair {}", check.description))?;
+			}
+			findings.push((check.description, found));
+		}
+
+		let violations: usize = transaction.prepare("PRAGMA foreign_key_check;")?
+			.query_map([], |_| Ok(()))?
+			.count();
+		if violations > 0 {
+			return Err(anyhow!("{violations} foreign key violations remain after the repairs"));
+		}
+
+		if !dry_run {
+			transaction.commit()?;
+		}
+		Ok(findings)
+	}
+
 	//QUICKMARKS---
 
-	///Deletes all the quickmarks in the database and adds marks for all the
-	///unmarked items in publishing order.
+	///Deletes all the quickmarks in the database and adds marks for all the unmarked
+	///(not starred nor snoozed) items. Items back from a snooze come first (the ones that woke
+	///up first, first), then the rest in publishing order (see Dates in the module docs for undated ones).
 	pub fn reset_quickmarks(&self) -> Result<()> {
 		let mut delete_quickmarks_st = self.db.prepare("DELETE FROM quickmarks;")?;
 		delete_quickmarks_st.execute([])?;
 
-		let mut add_quickmark_st = self.db.prepare(
-			"INSERT
-			INTO quickmarks (position, hash)
-			VALUES (?, ?); "
+		self.db.execute(
+			"INSERT INTO quickmarks (position, hash)
+			SELECT ROW_NUMBER() OVER (
+				ORDER BY woke_at IS NULL, woke_at, sort_date IS NULL, sort_date, first_id
+			), hash
+			FROM (
+				SELECT hash, MAX(snoozed_until) AS woke_at,
+					MIN(COALESCE(pub_date, first_seen)) AS sort_date, MIN(id) AS first_id
+				FROM items
+				WHERE read=0 AND starred_at IS NULL
+				GROUP BY hash
+				HAVING woke_at IS NULL OR woke_at <= CAST(strftime('%s', 'now') AS INTEGER)
+			);",
+			[]
 		)?;
 
-		let mut unmarked_items = self.all_unmarked_items()?;
-		unmarked_items.sort_by_key(|i| i.pub_date);
-		for i in 0..unmarked_items.len() {
-			let item = &unmarked_items[i];
-			add_quickmark_st.execute(params![i+1, item.title_or_description_hash])?;
-		}
-
 		Ok(())
 	}
 
 	///Get all the items in the database that have not been read, and with
-	///them get their quickmark position.
+	///them get their quickmark position. Ordered by position.
 	pub fn all_unmarked_items_with_quickmarks(&self) -> Result<Vec<(Item, i32)>> {
-		let mut get_st = self.db.prepare(
-			"SELECT hash, title_or_desc, url, pub_date, read, position
+		let mut get_st = self.db.prepare(&format!(
+			"SELECT {ITEM_COLUMNS}, position
 			FROM items INNER JOIN quickmarks USING(hash)
-			WHERE read=0;"
-		)?;
+			WHERE read=0
+			GROUP BY hash
+			ORDER BY position;"
+		))?;
 
 		let items = get_st.query_map([], |row| {
-			Ok( (
-				Item {
-					title_or_description_hash: row.get(0)?,
-					title_or_description: row.get(1)?,
-					link: row.get(2)?,
-					pub_date: row.get(3)?,
-					read: row.get(4)?
-				},
-				row.get(5)?)
-			)
+			Ok( (item_from_row(row)?, row.get(AFTER_ITEM_COLUMNS)?) )
 		})?;
 
 		Ok(items.flatten().collect())
 	}
 
-	///Creates new quickmarks for the items passed as argument.
+	///Creates new quickmarks for the items passed as argument, in the order they are passed.
 	///These marks don't overwrite, nor affect the marks already stored.
+	///Items that already have a mark (from another channel), that are read, starred or snoozed are skipped.
 	pub fn generate_quickmarks(&self, items: &[Item]) -> Result<()> {
-		//first step, find max quickmark
-		//then insert a quickmark for each item, counting up from the old_max
-
-		//Should we instead store the max quickmark somewhere ?
-		let mut max_quickmark_st = self.db.prepare(
-			"SELECT position FROM quickmarks;"
-		)?;
-		let max_quickmark = max_quickmark_st.query_map([], |row| row.get(0))?
-			.flatten()
-			.max()
-			.unwrap_or(0);
-
-		let mut insert_quickmark_st = self.db.prepare(
-			"INSERT into quickmarks (hash, position) VALUES (?, ?);"
+		//a single statement numbering up from the current last position (the primary key, so no scan),
+		//the hashes go in as a JSON array
+		let hashes: Vec<&str> = items.iter().map(|i| i.title_or_description_hash.as_str()).collect();
+		self.db.execute(
+			"INSERT INTO quickmarks (position, hash)
+			SELECT (SELECT COALESCE(MAX(position), 0) FROM quickmarks) + ROW_NUMBER() OVER (ORDER BY first_key), hash
+			FROM (
+				SELECT value AS hash, MIN(key) AS first_key
+				FROM json_each(?1)
+				WHERE value NOT IN (SELECT hash FROM quickmarks)
+					AND EXISTS (
+						SELECT 1 FROM items WHERE hash = value AND read = 0 AND starred_at IS NULL
+							AND (snoozed_until IS NULL OR snoozed_until <= CAST(strftime('%s', 'now') AS INTEGER))
+					)
+				GROUP BY value
+			);",
+			[serde_json::to_string(&hashes)?]
 		)?;
-		let mut position = max_quickmark + 1;
-		for i in items {
-			insert_quickmark_st.execute(params![i.title_or_description_hash, position])?;
-			position += 1;
-		}
 		Ok(())
 	}
 
@@ -327,21 +2351,19 @@ impl Database {
 		Ok(())
 	}
 
-	///Marks an item as read when passed it's quickmark.
-	///
-	///ignores invalid quickmarks.
+	///Marks an item as read when passed it's quickmark, fails if no item has it.
 	pub fn mark_as_read_with_quickmark(&self, mark: usize) -> Result<()> {
-		let mut statement = self.db.prepare(
+		let hash: String = self.db.query_row(
+			"SELECT hash FROM quickmarks WHERE position = ?;", [mark], |row| row.get(0)
+		).optional()?
+			.ok_or(ItemRefError::NotFound(ItemRef::Quickmark(mark)))?;
+
+		self.db.execute(
 			"UPDATE items
-			SET read=TRUE
-			WHERE hash=
-				(SELECT hash FROM quickmarks WHERE position = (?));"
+			SET read=TRUE, read_at = COALESCE(read_at, ?2), snoozed_until = NULL, auto_read = 0
+			WHERE hash = ?1;",
+			params![hash, Utc::now().timestamp()]
 		)?;
-		statement.execute(params![mark])?;
-
-		self.db.prepare("DELETE FROM quickmarks WHERE position = (?);")?
-			.execute(params![mark])?;
-
-		Ok(())
+		self.remove_quickmark(&hash)
 	}
 }
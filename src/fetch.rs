@@ -0,0 +1,274 @@
+//! Getting feeds from the network (or from disk), within limits.
+//!
+//! A feed is someone else's server talking to us. During a cron __up__ a single broken
+//! or hostile feed should not be able to eat all of our memory or bloat the database,
+//! so every feed we read is capped in size, time and number of items.
+//!
+//! The limits can be changed through environment variables:
+//!
+//! ```text
+//! CORKBOARD_MAX_FEED_BYTES    <- Largest feed we'll read, in bytes (default 10MiB).
+//! CORKBOARD_MAX_ITEMS         <- Items kept per feed, the rest are dropped (default 1000).
+//! CORKBOARD_MAX_FIELD_LENGTH  <- Longest title, description, content or link, in characters (default 16384).
+//! CORKBOARD_FETCH_TIMEOUT     <- Seconds a whole request (connecting and reading) may take (default 30).
+//! ```
+
+use anyhow::{Context, Result};
+use encoding_rs::{Encoding, UTF_8};
+use reqwest::{blocking::Client, header::{CONTENT_TYPE, HeaderMap}};
+use url::Url;
+
+use std::{
+	env,
+	fmt,
+	error,
+	fs::File,
+	io::{self, Read},
+	num::{NonZeroU64, NonZeroUsize},
+	path::Path,
+	str::FromStr,
+	time::Duration
+};
+
+use crate::{
+	rss::{Channel, Item},
+	xml_handler::xml_to_rss
+};
+
+///How much we are willing to take from a single feed.
+#[derive(Debug, Clone)]
+pub struct Limits {
+	///Largest response (or file) we will read, in bytes
+	pub max_feed_bytes: u64,
+	///Items kept from a single feed, counting from the top
+	pub max_items: usize,
+	///Longest title, description, content or link we keep, in characters
+	pub max_field_length: usize,
+	///Time a whole request may take, from connecting to reading the last byte
+	pub fetch_timeout: Duration
+}
+impl Default for Limits {
+	fn default() -> Self {
+		Self {
+			max_feed_bytes: 10 * 1024 * 1024,
+			max_items: 1000,
+			max_field_length: 16 * 1024,
+			fetch_timeout: Duration::from_secs(30)
+		}
+	}
+}
+impl Limits {
+	///The default limits, overridden by any of the environment variables that are set.
+	pub fn from_env() -> Result<Self> {
+		let defaults = Self::default();
+
+		Ok(Self {
+			max_feed_bytes: env_or::<NonZeroU64, _>("CORKBOARD_MAX_FEED_BYTES", defaults.max_feed_bytes)?,
+			max_items: env_or::<NonZeroUsize, _>("CORKBOARD_MAX_ITEMS", defaults.max_items)?,
+			max_field_length: env_or::<NonZeroUsize, _>("CORKBOARD_MAX_FIELD_LENGTH", defaults.max_field_length)?,
+			fetch_timeout: Duration::from_secs(
+				env_or::<NonZeroU64, _>("CORKBOARD_FETCH_TIMEOUT", defaults.fetch_timeout.as_secs())?
+			)
+		})
+	}
+
+	///A client for making requests that respects the time limits.
+	pub fn client(&self) -> Result<Client> {
+		Client::builder()
+			.timeout(self.fetch_timeout)
+			.connect_timeout(self.fetch_timeout)
+			.build()
+			.context("Could not build the client for network requests")
+	}
+
+	///Cut a parsed channel down to size.
+	///
+	///Only the first _max_items_ items are kept, long titles, descriptions and contents are truncated
+	///and items with links that are too long are dropped (a truncated link is useless).
+	///Returns a note for every cut made, so that the user may know about it.
+	pub fn enforce(&self, channel: &mut Channel) -> Vec<String> {
+		let mut notes = Vec::new();
+		let max_length = self.max_field_length;
+
+		if channel.items.len() > self.max_items {
+			notes.push(format!("Feed has {} items, only the first {} were kept",
+							   channel.items.len(), self.max_items));
+			channel.items.truncate(self.max_items);
+		}
+
+		if let Some(title) = truncate_chars(&channel.title, max_length) {
+			notes.push(format!("Channel title is longer than {max_length} characters, it was truncated"));
+			channel.title = title;
+		}
+		if let Some(description) = truncate_chars(&channel.description, max_length) {
+			notes.push(format!("Channel description is longer than {max_length} characters, it was truncated"));
+			channel.description = description;
+		}
+
+		let items_before = channel.items.len();
+		channel.items.retain(|i| i.link.as_ref().is_none_or(|l| l.chars().count() <= max_length));
+		if channel.items.len() < items_before {
+			notes.push(format!("{} items with links longer than {max_length} characters were dropped",
+							   items_before - channel.items.len()));
+		}
+
+		for item in channel.items.iter_mut() {
+			if let Some(text) = truncate_chars(&item.title_or_description, max_length) {
+				notes.push(format!("An item's title is longer than {max_length} characters, it was truncated"));
+				//The hash depends on the text, so the item has to be rebuilt
+				*item = Item {
+					description: item.description.take(),
+					content: item.content.take(),
+					untitled: item.untitled,
+					..Item::new(text, item.link.take(), item.pub_date)
+				};
+			}
+			if let Some(description) = item.description.as_deref().and_then(|d| truncate_chars(d, max_length)) {
+				notes.push(format!("An item's description is longer than {max_length} characters, it was truncated"));
+				item.description = Some(description);
+			}
+			if let Some(content) = item.content.as_deref().and_then(|c| truncate_chars(c, max_length)) {
+				notes.push(format!("An item's content is longer than {max_length} characters, it was truncated"));
+				item.content = Some(content);
+			}
+		}
+
+		notes
+	}
+}
+
+///Errors from going over one of the limits
+#[derive(Debug)]
+pub enum LimitError {
+	///The feed is bigger than max_feed_bytes
+	FeedTooLarge(u64),
+	///An environment variable holds something that isn't a valid limit
+	InvalidLimit(&'static str, String)
+}
+impl fmt::Display for LimitError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			LimitError::FeedTooLarge(limit) =>
+				write!(f, "Feed is larger than the limit of {limit} bytes (see $CORKBOARD_MAX_FEED_BYTES)"),
+			LimitError::InvalidLimit(variable, value) =>
+				write!(f, "${variable} must be a positive number, not \"{value}\"")
+		}
+	}
+}
+impl error::Error for LimitError {}
+
+///Read an environment variable as a limit, use the default if it is not set.
+///
+///The value is parsed as _N_ (a NonZero type), a limit of 0 would refuse every feed.
+fn env_or<N: FromStr + Into<T>, T>(variable: &'static str, default: T) -> Result<T> {
+	match env::var(variable) {
+		Ok(value) => Ok(value.trim().parse::<N>()
+			.map_err(|_| LimitError::InvalidLimit(variable, value.clone()))?
+			.into()),
+		Err(_) => Ok(default)
+	}
+}
+
+///Returns the text cut to _max_ characters, or None if it already fits.
+fn truncate_chars(text: &str, max: usize) -> Option<String> {
+	let (cut_at, _) = text.char_indices().nth(max)?;
+	Some(text[..cut_at].to_string())
+}
+
+///Read everything from _source_, failing as soon as it goes over the size limit.
+pub fn read_limited<R: Read>(source: R, limits: &Limits) -> Result<Vec<u8>> {
+	let mut bytes = Vec::new();
+	//one byte more than allowed, so that we can tell a feed at the limit from one past it
+	source.take(limits.max_feed_bytes + 1).read_to_end(&mut bytes)?;
+
+	if bytes.len() as u64 > limits.max_feed_bytes {
+		return Err(LimitError::FeedTooLarge(limits.max_feed_bytes).into());
+	}
+	Ok(bytes)
+}
+
+///Decode the body of a response using the charset the server declared (utf-8 if none).
+fn decode_body(bytes: &[u8], headers: &HeaderMap) -> String {
+	let encoding = headers.get(CONTENT_TYPE)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|content_type| {
+			content_type.split(';')
+				.map(str::trim)
+				.find_map(|param| param.strip_prefix("charset="))
+		})
+		.and_then(|charset| Encoding::for_label(charset.trim_matches('"').as_bytes()))
+		.unwrap_or(UTF_8);
+
+	encoding.decode(bytes).0.into_owned()
+}
+
+///Request a feed with url parsing and error handling
+pub fn request_feed(feed_source: &str, client: &Client, limits: &Limits) -> Result<String> {
+	let parsed_source = Url::parse(feed_source)?;
+
+	let response = client.get(parsed_source).send()
+		.with_context(|| format!("Network request to feed failed for: {}", feed_source))?
+		.error_for_status()
+		.with_context(|| format!("Server refused the request for: {}", feed_source))?;
+
+	//Servers may lie about the length, but when they don't we can refuse without reading
+	if let Some(length) = response.content_length() {
+		if length > limits.max_feed_bytes {
+			return Err(LimitError::FeedTooLarge(limits.max_feed_bytes))
+				.with_context(|| format!("Refusing to download {}", feed_source));
+		}
+	}
+
+	let headers = response.headers().clone();
+	let bytes = read_limited(response, limits)
+		.with_context(|| format!("Could not read the feed from {}", feed_source))?;
+
+	Ok(decode_body(&bytes, &headers))
+}
+
+///Request a feed given by the user, returns the link that worked alongside the feed.
+///
+///url normalization:
+///If protocol defined by user, then use it.
+///If not, try https, then http
+pub fn request_user_feed(url: &str, client: &Client, limits: &Limits) -> Result<(String, String)> {
+	if url.starts_with("http") {
+		return Ok((String::from(url), request_feed(url, client, limits)?));
+	}
+
+	let https_link = format!("https://{url}");
+	let http_link = format!("http://{url}");
+
+	match request_feed(&https_link, client, limits) {
+		Ok(https_feed) => Ok((https_link, https_feed)),
+		Err(_) =>         Ok(( http_link.clone(), request_feed(&http_link, client, limits)? ))
+	}
+}
+
+///Request and parse a feed, already cut down to the limits.
+///Also returns the notes about what was cut.
+pub fn get_feed(url: &str, client: &Client, limits: &Limits) -> Result<(Channel, Vec<String>)> {
+	let xml_feed = request_feed(url, client, limits)?;
+	let mut channel = xml_to_rss(&xml_feed).context("Could not parse XML into RSS")?;
+	let notes = limits.enforce(&mut channel);
+	Ok((channel, notes))
+}
+
+///Get the xml of a feed that is not (necessarily) in the database.
+///
+///_source_ may be a path to a local file, "-" for stdin or an url.
+pub fn read_feed_source(source: &str, limits: &Limits) -> Result<String> {
+	let bytes = if source == "-" {
+		read_limited(io::stdin(), limits)
+			.context("Could not read feed from stdin")?
+	} else if Path::new(source).is_file() {
+		let file = File::open(source)
+			.with_context(|| format!("Could not open file {source}"))?;
+		read_limited(file, limits)
+			.with_context(|| format!("Could not read file {source}"))?
+	} else {
+		return Ok(request_user_feed(source, &limits.client()?, limits)?.1);
+	};
+
+	String::from_utf8(bytes).context("Feed is not valid utf-8")
+}
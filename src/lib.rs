@@ -0,0 +1,484 @@
+//! Corkboard as a library: everything the `corkboard` command does, without the printing.
+//!
+//! [Corkboard] wraps a database and the limits used when fetching feeds,
+//! its methods return what happened instead of writing it to the terminal.
+//! The feed parser is available on its own in [xml_handler] (and through [xml_to_rss]).
+//!
+//! ```no_run
+//! use corkboard::Corkboard;
+//!
+//! let board = Corkboard::open("corkdb")?;
+//! board.subscribe("example.com/feed.xml")?;
+//!
+//! let refresh = board.refresh()?;
+//! for (channel, new_items) in &refresh.updates {
+//!     println!("{}: {} new items", channel.title, new_items.len());
+//! }
+//! for (position, item) in board.unread()? {
+//!     println!("{position} {}", item.display_title());
+//! }
+//! board.mark(&[1])?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! Text coming from feeds is stored (and returned) as the feed sent it,
+//! pass it through [text::terminal_safe] before printing it to a terminal.
+
+pub mod rss;
+pub mod fetch;
+pub mod text;
+pub mod xml_handler;
+pub mod db;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+
+use std::{
+	env,
+	fs,
+	path::{Path, PathBuf}
+};
+
+use crate::{
+	db::{Database, DatabaseKey, ItemFilter, ItemRef, JournalEntry, QueryRows, RekeyedSnapshots, Setting},
+	fetch::{get_feed, request_user_feed, Limits},
+	rss::{Channel, Item}
+};
+
+pub use crate::xml_handler::{xml_to_rss, xml_to_rss_with_report};
+
+///Determine where the corkboard database is or should be, return the path
+///
+///The database (that is just an sqlite file) may be on:
+///  0. If $CORKDB_TEST is set and true, then we just use "corkdb"
+///  1. A custom path, defined by an environment variable $CORKDB,
+///     (full path including database name)
+///  2. In the XDG directory for program data: $XDG_DATA_HOME
+///  3. The default value for $XDG_DATA_HOME: $HOME/.local/share
+///  4. If all else fails, again use "./corkdb"
+pub fn default_database_path() -> Result<PathBuf> {
+	//0, testing environment
+	if let Ok(are_we_testing) = env::var("CORKDB_TEST") {
+		if &are_we_testing == "true" {
+			return Ok(Path::new("./corkdb").to_path_buf());
+		}
+	}
+
+	//1, custom path set with $CORKDB
+	if let Ok(custom_path) = env::var("CORKDB") {
+		return Ok(Path::new(&custom_path).to_path_buf());
+	}
+
+	//2, checking $XDG_DATA_HOME
+	if let Ok(xdg_data_home) = env::var("XDG_DATA_HOME") {
+		let xdg_path = Path::new(&xdg_data_home);
+		if xdg_path.exists() {
+			let corkboard_dir = xdg_path.join("./corkboard");
+			if !corkboard_dir.exists() {
+				fs::create_dir(corkboard_dir.clone())
+					.with_context(|| format!("Could not create directory at {corkboard_dir:?}"))?;
+			}
+			return Ok(corkboard_dir.join("./corkdb").to_path_buf());
+		}
+	}
+
+	//3, $HOME/.local/share
+	if let Ok(home_path) = env::var("HOME") {
+		let xdg_default_path = Path::new(&home_path).join(Path::new("./.local/share"));
+
+		if xdg_default_path.exists() {
+			let corkboard_dir = xdg_default_path.join("./corkboard");
+			if !corkboard_dir.exists() {
+				fs::create_dir(corkboard_dir.clone())
+					.with_context(|| format!("Could not create directory at {corkboard_dir:?}"))?;
+			}
+			return Ok(corkboard_dir.join("./corkdb").to_path_buf());
+		}
+	}
+
+	//4, basic behaviour
+	Ok(Path::new("./corkdb").to_path_buf())
+}
+
+///A feed that was just subscribed to.
+#[derive(Debug)]
+pub struct Subscription {
+	///The channel as stored, its link is the url that worked (maybe with a protocol added)
+	pub channel: Channel,
+	///Items added to the database, the ones the feed shares with other feeds are not repeated
+	pub new_items: Vec<Item>,
+	///Cuts made to the feed to keep it within the limits
+	pub notes: Vec<String>
+}
+
+///What happened while refreshing all the feeds.
+#[derive(Debug, Default)]
+pub struct Refresh {
+	///Every feed that had new items, along with those items
+	pub updates: Vec<(Channel, Vec<Item>)>,
+	///Feeds that could not be fetched or parsed, and why. The rest were still refreshed.
+	pub failures: Vec<(Channel, anyhow::Error)>,
+	///Cuts made to the feeds to keep them within the limits, as (feed link, note)
+	pub notes: Vec<(String, String)>,
+	///Items pruned after the refresh (only with the prune_after_up setting)
+	pub pruned: usize,
+	///Unread items marked as read because they waited longer than expire_unread_days
+	pub expired: usize
+}
+
+///A corkboard: a database of feeds and their items, along with the limits for fetching them.
+///
+///Every method that writes does so in a single transaction, it changes everything or nothing.
+///What they change is recorded in the journal, [Corkboard::undo] reverts it.
+pub struct Corkboard {
+	database: Database,
+	limits: Limits
+}
+impl Corkboard {
+	///Open (or create) the database at _path_, using the default limits.
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+		Ok(Self {
+			database: Database::setup(path)?,
+			limits: Limits::default()
+		})
+	}
+
+	///Open (or create) an encrypted database at _path_, if _key_ is given (see [db::DatabaseKey]).
+	pub fn open_with_key<P: AsRef<Path>>(path: P, key: Option<DatabaseKey>) -> Result<Self> {
+		Ok(Self {
+			database: Database::setup_with_key(path, key)?,
+			limits: Limits::default()
+		})
+	}
+
+	///Open the database the `corkboard` command would use (see [default_database_path]),
+	///with the key in the environment (see [db::KEY_VARIABLES]) if there is one.
+	pub fn open_default() -> Result<Self> {
+		Self::open_with_key(default_database_path()?, DatabaseKey::from_env()?)
+	}
+
+	///Encrypt the database at _path_ with _new_key_, or change its key when given the _current_ one.
+	///
+	///Fails if another corkboard is using the database. The snapshots get the new key too
+	///(or are deleted, see [Database::change_key]).
+	pub fn change_key<P: AsRef<Path>>(path: P, current: Option<DatabaseKey>, new_key: &DatabaseKey) -> Result<RekeyedSnapshots> {
+		Database::setup_with_key(path, current)?
+			.change_key(new_key)
+			.context("Could not change the key of the database")
+	}
+
+	///Use other limits for fetching feeds.
+	pub fn with_limits(self, limits: Limits) -> Self {
+		Self { limits, ..self }
+	}
+
+	///The database underneath, for anything the methods here don't cover.
+	pub fn database(&self) -> &Database {
+		&self.database
+	}
+
+	///Fetch a feed and add it, with all of its items, to the database.
+	///
+	///Without a protocol in _url_ https is tried first, then http.
+	pub fn subscribe(&self, url: &str) -> Result<Subscription> {
+		let (working_link, xml_feed) = request_user_feed(url, &self.limits.client()?, &self.limits)?;
+
+		let mut channel = xml_to_rss(&xml_feed)
+			.with_context(|| "Could not process xml")?;
+		let notes = self.limits.enforce(&mut channel);
+
+		//We keep the users original url and only change it if we had to use another protocol to find the feed
+		channel.link = working_link;
+
+		if channel.last_build_date.is_none() {
+			channel.last_build_date = Some(Utc::now())
+		}
+
+		//All or nothing, a failure halfway must not leave a channel without its items
+		let new_items = self.database.journaled(&format!("add {}", channel.link), || {
+			self.database.add_channel(&channel)
+				.with_context(|| "Failed to add channel to database")?;
+
+			let added_items = self.database.add_items(&channel, &channel.items)
+				.with_context(|| "Failed to add items")?;
+
+			self.database.generate_quickmarks(&added_items)
+				.context("Failed to create quickmarks for the items")?;
+			Ok(added_items)
+		})?;
+
+		Ok(Subscription { channel, new_items, notes })
+	}
+
+	///Fetch every feed and add the items we didn't have.
+	///
+	///A feed that fails is reported in the result and skipped, the others are still refreshed.
+	///Fails (changing nothing) if another refresh is running or if writing the new items fails.
+	pub fn refresh(&self) -> Result<Refresh> {
+		//held until we are done, a second update would fetch and insert the same items
+		let _lock = self.database.lock_updates()?;
+		let client = self.limits.client()?;
+		let mut refresh = Refresh::default();
+
+		let channels = self.database.all_channels()
+			.context("Failed to get all the channels for the update.")?;
+
+		//Nothing is written until all the feeds have been fetched.
+		let mut fetched: Vec<(Channel, Channel)> = Vec::new();
+		for c in channels {
+			let (feed, notes) = match get_feed(&c.link, &client, &self.limits) {
+				Ok(feed_and_notes) => feed_and_notes,
+				Err(e) => {
+					refresh.failures.push((c, e));
+					continue;
+				}
+			};
+			refresh.notes.extend(notes.into_iter().map(|note| (c.link.clone(), note)));
+
+			if let (Some(their_date), Some(our_date)) = (feed.last_build_date, c.last_build_date) {
+				if our_date >= their_date {
+					continue ;
+				}
+			}
+
+			fetched.push((c, feed));
+		}
+
+		//A single transaction, an interrupted or failed update leaves the database as it was.
+		//The database tells us which items are new, we don't load the old ones.
+		//Not journaled, updates often run from a timer and undo is for what the user did.
+		self.database.transaction(|| {
+			for (c, feed) in fetched {
+				let new_items = self.database.add_items(&c, &feed.items)
+					.with_context(|| format!("Could not insert new items from {}", c.link))?;
+				if new_items.is_empty() {
+					continue ;
+				}
+				self.database.generate_quickmarks(&new_items)?;
+				refresh.updates.push((c, new_items));
+			}
+
+			refresh.expired = self.database.expire_unread().context("Could not expire old unread items")?;
+
+			if self.database.setting(Setting::PruneAfterUp)? == Some(1) {
+				refresh.pruned = self.database.prune().context("Could not prune old items")?;
+			}
+			Ok(())
+		}).context("Update failed, the database was not changed")?;
+
+		Ok(refresh)
+	}
+
+	///All the feeds subscribed to (without their items).
+	pub fn feeds(&self) -> Result<Vec<Channel>> {
+		self.database.all_channels()
+			.context("Could not get channels from the database")
+	}
+
+	///Every unread item with its quickmark, in quickmark order (starred and snoozed items are left out).
+	///
+	///Like `corkboard new` the quickmarks are first renumbered in publishing order,
+	///so positions from earlier calls are no longer valid.
+	pub fn unread(&self) -> Result<Vec<(i32, Item)>> {
+		self.database.transaction(|| self.database.reset_quickmarks())
+			.context("Failed to write to database, reset quickmarks")?;
+
+		let items = self.database.all_unmarked_items_with_quickmarks()
+			.context("Could not get items from the database")?;
+		Ok(items.into_iter().map(|(item, position)| (position, item)).collect())
+	}
+
+	///Mark the items at these quickmark positions as read.
+	///Fails (marking none) if no item has one of them.
+	pub fn mark(&self, positions: &[usize]) -> Result<()> {
+		self.database.journaled(&format!("mark {}", list(positions)), || {
+			for p in positions {
+				self.database.mark_as_read_with_quickmark(*p)
+					.with_context(|| format!("Could not mark {p}"))?;
+			}
+			Ok(())
+		})
+	}
+
+	///Mark the items with these hashes as read.
+	pub fn mark_hashes<S: AsRef<str>>(&self, hashes: &[S]) -> Result<()> {
+		let command = format!("markhash {}", list(&hashes.iter().map(AsRef::as_ref).collect::<Vec<_>>()));
+		self.database.journaled(&command, || {
+			for hash in hashes {
+				self.database.mark_as_read(hash.as_ref(), true)
+					.context("Could not mark the article")?;
+				self.database.remove_quickmark(hash.as_ref())
+					.context("Could not delete quickmark associated with article")?;
+			}
+			Ok(())
+		})
+	}
+
+	///Mark every item as read, after taking a snapshot of the database.
+	pub fn mark_all(&self) -> Result<()> {
+		self.database.snapshot("mark-all")
+			.context("Could not take a snapshot of the database, nothing was marked")?;
+		self.database.journaled("mark --all", || {
+			let items = self.database.all_unmarked_items()
+				.context("Could not get open items from database")?;
+
+			for item in items {
+				self.database.mark_as_read(&item.title_or_description_hash, true)
+					.context("Could not mark the item")?;
+			}
+
+			self.database.reset_quickmarks()
+				.context("Failed to reset quickmarks")
+		})
+	}
+
+	///Mark items as unread again, they go back to the end of the queue.
+	///Fails (unmarking none) if one can't be found.
+	pub fn unmark(&self, items: &[ItemRef]) -> Result<()> {
+		self.database.journaled(&format!("unmark {}", list(items)), || {
+			for item in items {
+				for hash in self.database.resolve(item)? {
+					self.database.unmark(&hash)
+						.with_context(|| format!("Could not unmark {item}"))?;
+				}
+			}
+			Ok(())
+		})
+	}
+
+	///Star items, taking them out of the unread queue. Fails (starring none) if one can't be found.
+	pub fn star(&self, items: &[ItemRef]) -> Result<()> {
+		self.database.journaled(&format!("star {}", list(items)), || {
+			for item in items {
+				for hash in self.database.resolve(item)? {
+					self.database.star(&hash)
+						.with_context(|| format!("Could not star {item}"))?;
+				}
+			}
+			Ok(())
+		})
+	}
+
+	///Unstar items, the unread ones go back to the end of the queue.
+	///Fails (unstarring none) if one can't be found.
+	pub fn unstar(&self, items: &[ItemRef]) -> Result<()> {
+		self.database.journaled(&format!("unstar {}", list(items)), || {
+			for item in items {
+				for hash in self.database.resolve(item)? {
+					self.database.unstar(&hash)
+						.with_context(|| format!("Could not unstar {item}"))?;
+				}
+			}
+			Ok(())
+		})
+	}
+
+	///Snooze items until _until_, they are out of the unread queue until then and come back
+	///at the front of it. Fails (snoozing none) if one can't be found.
+	pub fn snooze(&self, items: &[ItemRef], until: DateTime<Utc>) -> Result<()> {
+		let command = format!("snooze {} until {}", list(items), until.format("%Y-%m-%d %H:%M UTC"));
+		self.database.journaled(&command, || {
+			for item in items {
+				for hash in self.database.resolve(item)? {
+					self.database.snooze(&hash, until)
+						.with_context(|| format!("Could not snooze {item}"))?;
+				}
+			}
+			Ok(())
+		})
+	}
+
+	///Every item still snoozed, the first to come back first.
+	pub fn snoozed(&self) -> Result<Vec<Item>> {
+		self.database.snoozed()
+			.context("Could not get the snoozed items from the database")
+	}
+
+	///Every starred item, read or not, most recently starred first.
+	pub fn starred(&self) -> Result<Vec<Item>> {
+		self.database.starred()
+			.context("Could not get the starred items from the database")
+	}
+
+	///Unsubscribe from a feed, deleting its items, after taking a snapshot of the database.
+	pub fn remove(&self, url: &str) -> Result<()> {
+		self.database.snapshot("remove")
+			.context("Could not take a snapshot of the database, nothing was removed")?;
+		self.database.journaled(&format!("remove {url}"), || self.database.remove_channel(url))
+			.context("Removing for channel failed")
+	}
+
+	///Items (read or not) matching a full text query, best matches first, with their quickmarks.
+	pub fn search(&self, query: &str, filter: &ItemFilter) -> Result<Vec<(Item, Option<i32>)>> {
+		self.database.search(query, filter)
+	}
+
+	///Items read in the range of the filter, most recently read first.
+	pub fn history(&self, filter: &ItemFilter) -> Result<Vec<Item>> {
+		self.database.history(filter)
+			.context("Could not get the reading history from the database")
+	}
+
+	///Run a query that only reads (see the views in [db]), everything it returned.
+	pub fn sql(&self, query: &str) -> Result<QueryRows> {
+		self.database.query(query)
+			.context("Query failed")
+	}
+
+	///Delete the read items the retention settings don't allow us to keep,
+	///returns how many were deleted. A snapshot of the database is taken first.
+	///
+	///Pruning at the end of [Corkboard::refresh] doesn't take snapshots, there would be one per update.
+	pub fn prune(&self) -> Result<usize> {
+		self.database.snapshot("prune")
+			.context("Could not take a snapshot of the database, nothing was pruned")?;
+		self.database.journaled("prune", || self.database.prune())
+			.context("Could not prune old items")
+	}
+
+	///Change a setting, for the feed at _feed_url_ or globally (see [Database::set_setting]).
+	pub fn set_setting(&self, setting: Setting, value: Option<i64>, feed_url: Option<&str>) -> Result<()> {
+		let value_text = value.map_or(String::from("--unset"), |value| setting.format_value(value));
+		let feed_text = feed_url.map(|url| format!(" --feed {url}")).unwrap_or_default();
+		let command = format!("config {} {value_text}{feed_text}", setting.name());
+		self.database.journaled(&command, || self.database.set_setting(setting, value, feed_url))
+	}
+
+	///Every operation that can be undone, most recent first.
+	pub fn journal(&self) -> Result<Vec<JournalEntry>> {
+		self.database.journal()
+			.context("Could not read the journal")
+	}
+
+	///Revert the last _count_ operations (most recent first), returns them.
+	///A snapshot of the database is taken first.
+	///
+	///Fails, changing nothing, if there aren't that many in the journal.
+	///Operations are kept for undo for keep_journal_days (see [db::JOURNAL_DAYS_DEFAULT]).
+	pub fn undo(&self, count: usize) -> Result<Vec<JournalEntry>> {
+		self.database.snapshot("undo")
+			.context("Could not take a snapshot of the database, nothing was undone")?;
+		self.database.transaction(|| self.database.undo(count))
+			.context("Undo failed, the database was not changed")
+	}
+
+	///Copy the database to _path_, safe to do while it's in use.
+	pub fn backup<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+		self.database.backup(path.as_ref())
+			.context("Backup failed")
+	}
+
+	///Replace the database with a backup (or snapshot), returns the snapshot taken of
+	///the database as it was before. Nothing changes if the backup can't be used.
+	pub fn restore<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+		//an update running now would write its items into the database we are replacing
+		let _lock = self.database.lock_updates()?;
+		self.database.restore(path.as_ref())
+			.context("Restore failed")
+	}
+}
+
+///Arguments of a command as they go in the journal, separated by commas
+fn list<T: std::fmt::Display>(arguments: &[T]) -> String {
+	arguments.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
@@ -4,36 +4,51 @@
 //! (without being too intrusive with what hasn't been)
 //!
 //! ```text
-//! add <url>		<- Add a new RSS feed (or Atom) to the app.
-//! up				<- Update all feeds, show the new articles.
-//! mark <item>		<- Mark article/item as read.
-//! new				<- Show all articles that haven't been marked.
-//! feeds			<- List all the feeds in the app.
-//! remove <url>	<- Remove a feed from the app.
+//! add <url>             <- Add a new RSS feed (or Atom) to the app.
+//! up                    <- Update all feeds, show the new articles.
+//! mark <item>           <- Mark article/item as read.
+//! new                   <- Show all articles that haven't been marked.
+//! feeds                 <- List all the feeds in the app.
+//! remove <url>          <- Remove a feed from the app.
+//! doctor                <- Find and repair inconsistencies in the database.
+//! validate <url>        <- Check a feed (or a local file) for problems, without saving it.
+//! parse <url>           <- Print a feed (or a local file, or stdin) as JSON, without saving it.
+//! search <query>        <- Find stored items (read or not) by their text.
+//! history               <- List the items read recently.
+//! prune                 <- Delete old read items, following the retention settings.
+//! config                <- Show or change the settings.
+//! sql <query>           <- Run a read only query against the database views.
+//! backup <path>         <- Copy the database to a file, safe while corkboard runs.
+//! restore <path>        <- Replace the database with a backup.
+//! encrypt               <- Encrypt the database (needs the encryption feature).
+//! rekey                 <- Change the key of an encrypted database.
+//! unmark <item>         <- Mark an item (by history position, hash or link) as unread again.
+//! star <item>           <- Star an item (by quickmark, hash or link), keeping it apart from the unread ones.
+//! unstar <item>         <- Unstar an item.
+//! starred               <- List the starred items.
+//! snooze <item> <when>  <- Hide an item from the unread ones until a later date.
+//! snoozed               <- List the snoozed items.
+//! undo [<count>]        <- Revert the last operations (undo --list shows them).
 //! ```
 
-pub mod rss;
-mod xml_handler;
-mod db;
 mod cli;
 
 use anyhow::{Context, Result};
-use reqwest::blocking::Client;
-use url::Url;
+use chrono::{DateTime, Local, Utc};
 
-use std::{
-	env::{self, args},
-	fs,
-	path::{Path, PathBuf}
-};
+use std::env::args;
 
-use crate::{
-	cli::Operation,
-	db::Database,
-	rss::{Channel, Item},
-	xml_handler::xml_to_rss
+use corkboard::{
+	Corkboard,
+	db::{DatabaseKey, EncryptionError, ItemFilter, ItemRef, RekeyedSnapshots, Setting, KEY_VARIABLES, NEW_KEY_VARIABLES},
+	default_database_path,
+	fetch::{read_feed_source, Limits},
+	text::terminal_safe,
+	xml_handler::{Diagnostic, xml_to_rss, xml_to_rss_with_report}
 };
 
+use crate::cli::{print_history, print_items, print_journal, print_rows, print_snoozed, OutputFormat, Operation};
+
 fn main() -> Result<()> {
 	let arguments = args().collect();
 	let op = cli::parse_arguments(arguments)?;
@@ -43,267 +58,297 @@ fn main() -> Result<()> {
 
 ///Set up database and run operation defined by op
 fn run_operation(op: Operation) -> Result<()> {
-	let database_path = find_database()?;
-	let database = Database::setup(database_path)?;
+	//Commands that should work without ever opening (or creating) the database
+	match op {
+		Operation::Validate(source) => return validate(&source),
+		Operation::Parse(source) => return parse(&source),
+		Operation::Help => return print_help(),
+		Operation::Encrypt => return encrypt(),
+		Operation::Rekey => return rekey(),
+		_ => {}
+	}
+
+	let board = Corkboard::open_with_key(default_database_path()?, DatabaseKey::from_env()?)?
+		.with_limits(Limits::from_env()?);
 
 	match op {
-		Operation::Add(url) => add(&database, &url),
-		Operation::Up => up(&database),
-		Operation::Feeds => feeds(&database),
-		Operation::New => new(&database),
-		Operation::Mark(positions) => mark_relative(&database, &positions),
-		Operation::MarkAll => mark_all(&database),
-		Operation::MarkHash(hashes) => mark(&database, &hashes),
-		Operation::Remove(feed_url) => remove(&database, &feed_url),
-		Operation::Help => print_help()
+		Operation::Add(url) => add(&board, &url),
+		Operation::Up => up(&board),
+		Operation::Feeds => feeds(&board),
+		Operation::New(format) => new(&board, format),
+		Operation::Mark(positions) => mark_relative(&board, &positions),
+		Operation::MarkAll => board.mark_all(),
+		Operation::MarkHash(hashes) => board.mark_hashes(&hashes),
+		Operation::Remove(feed_url) => board.remove(&feed_url),
+		Operation::Doctor(dry_run) => doctor(&board, dry_run),
+		Operation::Search(query, filter, format) => search(&board, &query, &filter, format),
+		Operation::History(filter, format) => history(&board, &filter, format),
+		Operation::Prune => prune(&board),
+		Operation::ShowConfig => show_config(&board),
+		Operation::SetConfig(setting, value, feed) => set_config(&board, setting, value, feed.as_deref()),
+		Operation::Sql(query, format) => print_rows(&board.sql(&query)?, format),
+		Operation::Backup(path) => board.backup(&path),
+		Operation::Restore(path) => restore(&board, &path),
+		Operation::Unmark(items) => board.unmark(&items),
+		Operation::Star(items) => board.star(&items),
+		Operation::Unstar(items) => board.unstar(&items),
+		Operation::Starred(format) => starred(&board, format),
+		Operation::Snooze(items, until) => snooze(&board, &items, until),
+		Operation::Snoozed(format) => print_snoozed(&board.snoozed()?, format),
+		Operation::Undo(count) => undo(&board, count),
+		Operation::UndoList(format) => print_journal(&board.journal()?, format),
+		Operation::Validate(_) | Operation::Parse(_) | Operation::Help |
+		Operation::Encrypt | Operation::Rekey => unreachable!("handled before opening the database")
 	}?;
 
 	Ok(())
 }
 
-///Determine where the corkboard database is or should be, return the path
-///
-///The database (that is just an sqlite file) may be on:
-///  0. If $CORKDB_TEST is set and true, then we just use "corkdb"
-///  1.	A custom path, defined by an environment variable $CORKDB,
-///    (full path including database name)
-///  2. In the XDG directory for program data: $XDG_DATA_HOME
-///  3. The default value for $XDG_DATA_HOME: $HOME/.local/share
-///  4. If all else fails, again use "./corkdb"
-fn find_database() -> Result<PathBuf> {
-	//0, testing environment
-	if let Ok(are_we_testing) = env::var("CORKDB_TEST") {
-		if &are_we_testing == "true" {
-			return Ok(Path::new("./corkdb").to_path_buf());
-		}
+///Add a feed and all of it's items into the database
+fn add(board: &Corkboard, url: &str) -> Result<()> {
+	let subscription = board.subscribe(url)?;
+	for note in subscription.notes {
+		eprintln!("{}", terminal_safe(&note));
 	}
+	Ok(())
+}
 
-	//1, custom path set with $CORKDB
-	if let Ok(custom_path) = env::var("CORKDB") {
-		return Ok(Path::new(&custom_path).to_path_buf());
+///Get updates from all rss feeds, display the items that are new in the database
+fn up(board: &Corkboard) -> Result<()> {
+	let refresh = board.refresh()?;
+
+	//a failed feed doesn't stop the others, we just let the user know
+	for (c, e) in &refresh.failures {
+		eprintln!("Failed to reach or parse: {} ({})", terminal_safe(&c.link), terminal_safe(&format!("{e:#}")));
+	}
+	for (link, note) in &refresh.notes {
+		eprintln!("{}: {note}", terminal_safe(link));
+	}
+	if refresh.expired > 0 {
+		eprintln!("Marked {} old unread items as read", refresh.expired);
+	}
+	if refresh.pruned > 0 {
+		eprintln!("Pruned {} old items", refresh.pruned);
 	}
 
-	//2, checking $XDG_DATA_HOME
-	if let Ok(xdg_data_home) = env::var("XDG_DATA_HOME") {
-		let xdg_path = Path::new(&xdg_data_home);
-		if xdg_path.exists() {
-			let corkboard_dir = xdg_path.join("./corkboard");
-			if !corkboard_dir.exists() {
-				fs::create_dir(corkboard_dir.clone())
-					.with_context(|| format!("Could not create directory at {corkboard_dir:?}"))?;
-			}
-			return Ok(corkboard_dir.join("./corkdb").to_path_buf());
+	for (c, new_items) in refresh.updates {
+		println!("Updates from \"{}\" ({})", terminal_safe(&c.title), terminal_safe(&c.link));
+		for i in new_items {
+			println!("\t {} at {}", i.display_title(), i.display_link().unwrap_or(String::from("<NO LINK>")));
 		}
 	}
+	Ok(())
+}
 
-	//3, $HOME/.local/share
-	if let Ok(home_path) = env::var("HOME") {
-		let xdg_default_path = Path::new(&home_path).join(Path::new("./.local/share"));
-
-		if xdg_default_path.exists() {
-			let corkboard_dir = xdg_default_path.join("./corkboard");
-			if !corkboard_dir.exists() {
-				fs::create_dir(corkboard_dir.clone())
-					.with_context(|| format!("Could not create directory at {corkboard_dir:?}"))?;
-			}
-			return Ok(corkboard_dir.join("./corkdb").to_path_buf());
+///List all feeds in the database.
+fn feeds(board: &Corkboard) -> Result<()> {
+	let channels = board.feeds()?;
+	if channels.len() == 0 {
+		println!("No RSS feeds in the database");
+	} else {
+		for c in channels {
+			println!("{}", terminal_safe(&c.link));
 		}
 	}
-
-	//4, basic behaviour
-	return Ok(Path::new("./corkdb").to_path_buf());
+	Ok(())
 }
 
-///Request a feed with url parsing and error handling
-fn request_feed(feed_source: &str) -> Result<String> {
-	let parsed_source = Url::parse(feed_source)?;
-
-	reqwest::blocking::get(parsed_source)
-		.with_context(|| format!("Network request to feed failed for: {}", feed_source))?
-		.text()
-		.with_context(|| "Could not turn feed into a string")
+///Show all the items not yet marked (read by the user)
+fn new(board: &Corkboard, format: OutputFormat) -> Result<()> {
+	let items: Vec<_> = board.unread()?
+		.into_iter()
+		.map(|(position, item)| (item, Some(position)))
+		.collect();
+	print_items(&items, format)
 }
 
-///Add a feed and all of it's items into the database
-fn add(database: &Database, url: &str) -> Result<()> {
-	//url normalization
-	//If protocol defined by user, then use it.
-	//If not, try https, then http
-	let (working_link, xml_feed) = match url.find("http") {
-		Some(idx) if idx==0 => (String::from(url), request_feed(url)?),
-		_ => {
-			let https_link = format!("https://{url}");
-			let http_link = format!("http://{url}");
-
-			match request_feed(&https_link) {
-				Ok(https_feed) => (https_link, https_feed),
-				Err(_) =>         ( http_link.clone(), request_feed(&http_link)? )
-			}
-		}
-	};
-
-	let mut channel = xml_to_rss(&xml_feed)
-		.with_context(|| "Could not process xml")?;
-
-	//We keep the users original url and only change it if we had to use another protocol to find the feed
-	channel.link = working_link;
+///Find items (read or not) by their title, description or content.
+///Doesn't change the quickmarks, so the positions shown stay valid for __mark__.
+fn search(board: &Corkboard, query: &str, filter: &ItemFilter, format: OutputFormat) -> Result<()> {
+	let items = board.search(query, filter)?;
+	print_items(&items, format)
+}
 
-	if let None = channel.last_build_date {	
-		channel.last_build_date = Some(chrono::Utc::now())
+///Mark items in the database as read when given their positions
+///as printed by the _new_ command.
+fn mark_relative(board: &Corkboard, positions:&[usize]) -> Result<()> {
+	//All or nothing: a position without an item is most likely a typo (or a stale
+	//list), marking the others would leave the user guessing which ones went through.
+	board.mark(positions)?;
+	for p in positions {
+		println!("Marked item {p}");
 	}
-
-	database.add_channel(&channel)
-		.with_context(|| "Failed to add channel to database")?;
-
-	//could and should I "unroll" the changes ?
-	database.add_items(&channel, &channel.items)
-		.with_context(|| "Failed to add items")?;
-
-	database.generate_quickmarks(&channel.items)
-		.context("Failed to create quickmarks for the items")?;
-
 	Ok(())
-} 
+}
 
-///Get updates from all rss feeds, display the items that are new in the database
-fn up(database: &Database) -> Result<()> {
-	let client = Client::new();
-
-	let channels = database.all_channels_with_items()
-		.context("Failed to get all the channels for the update.")?;
-
-	//here we would like to ignore the error if one fails,
-	//we'll post something about a failure but keep running with the
-	//rest of the channels
-	
-	for c in channels {
-		let Ok(feed) = get_feed(&c.link, &client) else {
-			eprintln!("Failed to reach or parse: {}", c.link);
-			continue;
-		};
-
-		if let (Some(their_date), Some(our_date)) = (feed.last_build_date, c.last_build_date) {
-			if our_date >= their_date {
-				continue ;
-			}
-		}
+///List the items read in a range of time (all of them by default), most recent first.
+fn history(board: &Corkboard, filter: &ItemFilter, format: OutputFormat) -> Result<()> {
+	print_history(&board.history(filter)?, format)
+}
 
-		let new_items:Vec<Item> = feed.items.into_iter()
-			.filter(|i| !c.items.contains(i))
-			.collect();
+///List the starred items, most recently starred first.
+fn starred(board: &Corkboard, format: OutputFormat) -> Result<()> {
+	let items: Vec<_> = board.starred()?
+		.into_iter()
+		.map(|item| (item, None))
+		.collect();
+	print_items(&items, format)
+}
 
-		if new_items.len() == 0 {
-			continue ;
-		}
+///Snooze items, telling the user when they come back (in local time).
+fn snooze(board: &Corkboard, items: &[ItemRef], until: DateTime<Utc>) -> Result<()> {
+	board.snooze(items, until)?;
+	println!("Snoozed until {}", until.with_timezone(&Local).format("%Y-%m-%d %H:%M"));
+	Ok(())
+}
 
-		if let Err(db_e) = database.add_items(&c, &new_items) {
-			eprintln!("Could not insert new items into database: {db_e}");
-			continue ;
-		}
-		//I'm unsure if we should update quickmarks on up since we aren't displaying them ever ?
-		database.generate_quickmarks(&new_items)?;
+///Delete the read items the retention settings don't allow us to keep.
+fn prune(board: &Corkboard) -> Result<()> {
+	let pruned = board.prune()?;
+	println!("Pruned {pruned} items");
+	Ok(())
+}
 
-		println!("Updates from \"{}\" ({})", c.title, c.link);
-		for i in new_items {
-			println!("\t {} at {}", i.title_or_description, i.link.unwrap_or(String::from("<NO LINK>")));
+///Print every setting, the global values first then the ones of each feed.
+fn show_config(board: &Corkboard) -> Result<()> {
+	let database = board.database();
+	for setting in Setting::ALL {
+		match database.setting(*setting)? {
+			Some(value) => println!("{} = {}", setting.name(), setting.format_value(value)),
+			None => println!("{} is not set", setting.name())
 		}
 	}
+	for (feed, setting, value) in database.feed_settings()? {
+		println!("{}: {} = {}", terminal_safe(&feed), setting.name(), setting.format_value(value));
+	}
 	Ok(())
 }
 
-fn get_feed(url: &str, req_client: &Client) -> Result<Channel> {
-	let xml_feed = req_client.get(url)
-		.send().with_context(|| format!("Request to:{} failed", url))?
-		.text().with_context(|| format!("Response from:{}, could not be turned into text", url))?;
-	xml_to_rss(&xml_feed).context("Could not parse XML into RSS")
+///Change a setting, for a single feed if one is given.
+fn set_config(board: &Corkboard, setting: Setting, value: Option<i64>, feed: Option<&str>) -> Result<()> {
+	board.set_setting(setting, value, feed)
+		.with_context(|| format!("Could not change {}", setting.name()))
 }
 
-///List all feeds in the database.
-fn feeds(database: &Database) -> Result<()> {
-	let channels = database.all_channels()
-		.context("Could not get channels from the database")?;
-	if channels.len() == 0 {
-		println!("No RSS feeds in the database");
-	} else {
-		for c in channels {
-			println!("{}", c.link);
-		}
+///Revert the last _count_ operations, telling the user which ones.
+fn undo(board: &Corkboard, count: usize) -> Result<()> {
+	for entry in board.undo(count)? {
+		println!("Undid {}", terminal_safe(&entry.command));
 	}
 	Ok(())
 }
 
-///Show all the items not yet marked (read by the user)
-fn new(database: &Database) -> Result<()> {
-	database.reset_quickmarks()
-		.context("Failed to write to database, reset quickmarks")?;
-
-	let mut items:Vec<(Item, i32)> = database.all_unmarked_items_with_quickmarks()
-		.context("Could not get items from the database")?;
-	items.sort_by_key(|t| t.1);
-
-	for (item, position) in items {
-		//FIX: move displaying to cli module
-		println!("{} -> [{}] {}",
-				 position,
-				 item.link.as_ref().unwrap_or(&String::from("No link")),
-				 item.title_or_description);
-	}
-
+///Replace the database with a backup, telling the user where the old one went.
+fn restore(board: &Corkboard, path: &str) -> Result<()> {
+	let snapshot = board.restore(path)?;
+	println!("Restored {}, the database as it was is in {}",
+			 terminal_safe(path),
+			 terminal_safe(&snapshot.to_string_lossy()));
 	Ok(())
 }
 
-///Mark an item in the database as read.
-fn mark(database: &Database, hash_string:&[String]) -> Result<()> {
-	for hash in hash_string {
-		database.mark_as_read(hash, true)
-			.context("Could not mark the article")?;
-		database.remove_quickmark(hash)
-			.context("Could not delete quickmark associated with article")?;
-	}
+///Encrypt the (plaintext) database with the key in $CORKDB_KEY or $CORKDB_KEY_FILE.
+fn encrypt() -> Result<()> {
+	let key = DatabaseKey::from_env()?
+		.ok_or(EncryptionError::NoKey(KEY_VARIABLES.0, KEY_VARIABLES.1))?;
+	let path = default_database_path()?;
+	let snapshots = Corkboard::change_key(&path, None, &key)?;
+	report_snapshots(&snapshots);
 	Ok(())
 }
 
-///Mark all items as read.
-fn mark_all(database: &Database) -> Result<()> {
-	let items = database.all_unmarked_items()
-		.context("Could not get open items from database")?;
+///Change the key of the database, from $CORKDB_KEY (or _FILE) to $CORKDB_NEW_KEY (or _FILE).
+fn rekey() -> Result<()> {
+	let key = DatabaseKey::from_env()?
+		.ok_or(EncryptionError::NoKey(KEY_VARIABLES.0, KEY_VARIABLES.1))?;
+	let new_key = DatabaseKey::from_variables(NEW_KEY_VARIABLES)?
+		.ok_or(EncryptionError::NoKey(NEW_KEY_VARIABLES.0, NEW_KEY_VARIABLES.1))?;
+	let path = default_database_path()?;
+	let snapshots = Corkboard::change_key(&path, Some(key), &new_key)?;
+	report_snapshots(&snapshots);
+	Ok(())
+}
 
-	for item in items {
-		database.mark_as_read(&item.title_or_description_hash, true)
-			.context("Coulld not mark the item")?;
+///Tell the user what happened to the snapshots on a key change, backups are up to them.
+fn report_snapshots(snapshots: &RekeyedSnapshots) {
+	if snapshots.reencrypted > 0 {
+		println!("Encrypted {} snapshots with the new key", snapshots.reencrypted);
 	}
+	if snapshots.deleted > 0 {
+		println!("Deleted {} snapshots that used an older key", snapshots.deleted);
+	}
+	eprintln!("Backups taken before still use the old key, or none. Delete them if that's a problem.");
+}
 
-	database.reset_quickmarks()
-		.context("Failed to reset quickmarks")?;
+///Check the database for inconsistent data, repairing it unless _dry_run_.
+fn doctor(board: &Corkboard, dry_run: bool) -> Result<()> {
+	let findings = board.database().doctor(dry_run)
+		.context("Could not check the database")?;
+
+	let mut problems = 0;
+	for (description, found) in findings {
+		if found > 0 {
+			let action = if dry_run { "found" } else { "repaired" };
+			println!("{description}: {found} {action}");
+			problems += found;
+		}
+	}
 
+	if problems == 0 {
+		println!("No problems found");
+	}
 	Ok(())
 }
 
-///Mark an item in the database as read when given it's position
-///as printed by the _new_ command.
-fn mark_relative(database: &Database, positions:&[usize]) -> Result<()> {
-	//This is one of the places where I am unsure about how to manage errors
-	//I belive that for myself the easiest option is to just ignore
-	//failed mark attempts, but I can see how perhaps a user would prefer
-	//that the whole command fails.
-	//
-	//For now I am chosing to ignore failed marks and keep going.
-	//I am printing a message still.
+///Fetch and parse a feed (or read a local file) without touching the database,
+///then print everything that looks wrong with it.
+///
+///Fails if the feed can't be used at all (unreachable, not XML, missing required tags),
+///otherwise just reports.
+fn validate(source: &str) -> Result<()> {
+	let limits = Limits::from_env()?;
+	let xml_feed = read_feed_source(source, &limits)?;
 
-	for p in positions {
-		match database.mark_as_read_with_quickmark(*p) {
-			Ok(_) => { println!("Marked item {p}"); },
-			Err(e) => { println!("Could not mark {p} due to {e}. Moving on"); }
-		};
+	let (channel, report) = xml_to_rss_with_report(&xml_feed);
+
+	match report.format {
+		Some(format) => println!("Format: {format}"),
+		None => println!("Format: unknown")
+	}
+	for diagnostic in &report.diagnostics {
+		println!("{}", terminal_safe(&diagnostic.to_string()));
+	}
+
+	let mut channel = channel.context("Feed is not valid")?;
+	for note in limits.enforce(&mut channel) {
+		println!("{}", terminal_safe(&note));
 	}
+	let skipped = report.diagnostics.iter()
+		.filter(|d| matches!(d, Diagnostic::SkippedItem { .. }))
+		.count();
+	println!("Channel \"{}\" ({}): {} items read, {} skipped",
+			 terminal_safe(&channel.title),
+			 terminal_safe(&channel.link),
+			 channel.items.len(),
+			 skipped);
 
 	Ok(())
 }
 
-///Remove feed and it's items from the database
-fn remove(database: &Database, url:&str) -> Result<()> {
-	database.remove_channel(url)
-		.context("Removing for channel failed")
+///Print the channel (and its items) parsed from a feed as JSON, without touching the database.
+fn parse(source: &str) -> Result<()> {
+	let limits = Limits::from_env()?;
+	let xml_feed = read_feed_source(source, &limits)?;
+	let mut channel = xml_to_rss(&xml_feed)
+		.context("Could not process xml")?;
+	for note in limits.enforce(&mut channel) {
+		eprintln!("{}", terminal_safe(&note));
+	}
+
+	let json = serde_json::to_string_pretty(&channel)
+		.context("Could not turn the feed into JSON")?;
+	println!("{json}");
+	Ok(())
 }
 
 ///Print the help message for the program
@@ -317,11 +362,56 @@ Commands:
   add <url>             Subscribe to a feed with url <url>.
   up                    Update all feeds then display all the items/posts that were added.
   feeds                 List all subscribed feeds.
-  new                   Show all items not marked as read (does not update channels).
-  mark <number>         Mark an item at position <number> as read. Positions come from corkboard new.
+  new [--format text|json]
+                        Show all items not marked as read (does not update channels).
+  mark <number>...      Mark the items at these positions as read, or none if one doesn't exist.
+                        Positions come from corkboard new.
   mark --all            Mark all items as read.
+  unmark <item>...      Mark items as unread again, given by hash, link or position in corkboard history
+                        (1 is the item read last). They get a new position at the end of corkboard new.
+  star <item>...        Star items, given by quickmark, hash or link. Starred items leave the unread list
+                        (and are never pruned) until unstarred.
+  unstar <item>...      Unstar items, the unread ones are back in corkboard new.
+  starred [--format text|json]
+                        List the starred items, most recently starred first.
+  snooze <item>... <duration|date>
+                        Hide items (given by quickmark, hash or link) from corkboard new until later:
+                        in 12h, 3d or 2w, or on a date (YYYY-MM-DD or RFC 3339). Then they come back first.
+  snoozed [--format text|json]
+                        List the snoozed items, the first to come back first.
+  undo [<count>]        Revert the last operation (or the last <count>) that changed the database, up aside.
+  undo --list [--format text|json]
+                        List the operations that can be undone, the next one to undo first.
   remove <url>          Unsuscribe from a feed and delete all of it's items from the database.
+  doctor [--dry-run]    Find and repair inconsistencies in the database (only report them with --dry-run).
+  validate <url|file>   Check a feed for problems without adding it, fails if it can't be used.
+  parse <url|file|->    Print a feed as JSON (items include their hashes) without adding it.
+  search <query> [--read|--unread] [--feed <url>] [--since <date>] [--until <date>] [--format text|json]
+                        Find items by their title, description or content, best matches first.
+                        Use \"quotes\" for phrases. Dates are YYYY-MM-DD or RFC 3339.
+  history [--feed <url>] [--since <date>] [--until <date>] [--format text|json]
+                        List the items read (in that range of time), most recent first.
+  prune                 Delete old read items, following the retention settings.
+  config                Show all the settings.
+  config <setting> <value>|--unset [--feed <url>]
+                        Change a setting, for a single feed with --feed. See the settings below.
+  sql <query> [--format text|json]
+                        Run a read only SQL query. Use the v1_unread_items, v1_feed_stats and v1_history views,
+                        the tables underneath may change between versions.
+  backup <path>         Copy the database to <path>, safe to run while corkboard is in use.
+  restore <path>        Replace the database with the backup at <path>, once it's checked.
+  encrypt               Encrypt the database with the key in $CORKDB_KEY (or the file in $CORKDB_KEY_FILE).
+  rekey                 Change the key of the database to $CORKDB_NEW_KEY (or the file in $CORKDB_NEW_KEY_FILE).
   help                  Show this help message.
+
+Settings:
+  keep_read_days        Prune items read more than this many days ago (or never).
+  keep_items            Prune read items past this many in a feed, newest first (or never).
+  prune_after_up        Prune at the end of every update, true or false (global only).
+  expire_unread_days    Mark as read the unread items waiting for more than this many days, on every update
+                        (or never, the default).
+  keep_journal_days     Operations can be undone for this many days, 30 unless set (or never to keep them all,
+                        global only).
 ";
 	println!("{msg}");
 	Ok(())
@@ -1,6 +1,6 @@
 //! RSS is a protocol based on XML files, the tree structure is:
 //!
-//! ```
+//! ```text
 //! rss
 //!	-channel
 //! --title
@@ -11,6 +11,7 @@
 //! ---title
 //! ---link
 //! ---description
+//! ---content:encoded <--- full text of the post (optional)
 //! ---guid <--- useless?
 //! ---pubdate
 //! ```
@@ -19,13 +20,16 @@ use chrono::{
 	offset::Utc,
 	DateTime
 };
+use serde::Serialize;
 use sha2::{Sha256, Digest};
 
+use crate::text::{plain_text, synthetic_title, terminal_safe};
+
 ///Represents a single item in an RSS channel.
 ///
 ///It's only ensured that either a description tag or a title tag
 ///will be present in it.
-#[derive(Debug, Eq)]
+#[derive(Debug, Clone, Eq, Serialize)]
 pub struct Item {
 	///The title of the item or in it's absence the description
 	pub title_or_description: String,
@@ -37,7 +41,23 @@ pub struct Item {
 	///Date that the item was published
 	pub pub_date: Option<DateTime<Utc>>,
 	///Whether the user has read or not this item
-	pub read: bool
+	pub read: bool,
+	///Summary of the item, (kept even when it's standing in for the title)
+	pub description: Option<String>,
+	///Full content of the item (content:encoded in RSS, content in Atom)
+	pub content: Option<String>,
+	///When corkboard first fetched the item (None for items not from the database)
+	pub first_seen: Option<DateTime<Utc>>,
+	///When the user marked the item as read
+	pub read_at: Option<DateTime<Utc>>,
+	///When the user starred the item, None if it's not starred
+	pub starred_at: Option<DateTime<Utc>>,
+	///Until when the item is snoozed (if it's in the past the item is back in the queue)
+	pub snoozed_until: Option<DateTime<Utc>>,
+	///Whether corkboard marked it as read itself because it waited too long (see expire_unread_days)
+	pub auto_read: bool,
+	///The feed gave no title, title_or_description holds the description
+	pub untitled: bool
 }
 impl Item {
 
@@ -57,9 +77,36 @@ impl Item {
 			title_or_description_hash: format!("{:016x}", hash),
 			link,
 			pub_date,
-			read: false
+			read: false,
+			description: None,
+			content: None,
+			first_seen: None,
+			read_at: None,
+			starred_at: None,
+			snoozed_until: None,
+			auto_read: false,
+			untitled: false
+		}
+	}
+
+	///The title as it should be shown to the user, a single line of plain text.
+	///
+	///Markup is stripped, and when a description is standing in for a missing
+	///title it's shortened to its first sentence. Real titles are shown whole.
+	///Safe to print to a terminal.
+	pub fn display_title(&self) -> String {
+		let title = plain_text(&self.title_or_description);
+		if self.untitled {
+			terminal_safe(&synthetic_title(&title))
+		} else {
+			terminal_safe(&title)
 		}
 	}
+
+	///The link as it should be shown to the user, safe to print to a terminal.
+	pub fn display_link(&self) -> Option<String> {
+		self.link.as_deref().map(terminal_safe)
+	}
 }
 impl PartialEq for Item {
 	fn eq(&self, other: &Self) -> bool {
@@ -70,14 +117,14 @@ impl std::fmt::Display for Item {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		write!(f, "{} -> [{}] {}",
 			   self.title_or_description_hash,
-			   self.link.as_ref().unwrap_or(&String::from("No link")),
-			   self.title_or_description)
+			   self.display_link().unwrap_or(String::from("No link")),
+			   self.display_title())
 	}
 }
 
 
 ///RSS channel (which should correspond to a whole website or blog).
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Channel {
 	///Name of the channel
 	pub title:String,
@@ -0,0 +1,209 @@
+//! Turning the text that feeds give us into something we can show in a single line.
+//!
+//! Feeds are free to put HTML (escaped or in CDATA blocks) into titles and descriptions.
+//! When an item has no title we fall back to the description, so without some cleaning
+//! we would end up printing whole paragraphs of markup.
+//!
+//! Everything coming from a feed also has to go through [terminal_safe] before being printed,
+//! a feed could otherwise send escape sequences that our user's terminal would act on.
+//!
+//! Nothing here is stored, the database always keeps the text as the feed sent it.
+
+///Maximum length (in characters) of a title made up from a description.
+pub const SYNTHETIC_TITLE_LENGTH: usize = 80;
+
+///Removes HTML tags, decodes entities and collapses all whitespace into single spaces.
+pub fn plain_text(source: &str) -> String {
+	let without_tags = strip_tags(source);
+	let decoded = decode_entities(&without_tags);
+	decoded.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+///Makes a short title out of a description, for items that have none.
+///
+///Texts that already fit are returned as they are, otherwise we keep the first sentence
+///if it fits, or cut at the last word that does and add an ellipsis.
+pub fn synthetic_title(text: &str) -> String {
+	if text.chars().count() <= SYNTHETIC_TITLE_LENGTH {
+		return text.to_string();
+	}
+
+	//byte index right after the end of the first sentence, if it's short enough
+	let first_sentence_end = text.char_indices()
+		.take(SYNTHETIC_TITLE_LENGTH)
+		.find(|&(i, c)| {
+			matches!(c, '.' | '!' | '?') &&
+			text[i + c.len_utf8()..].starts_with(' ')
+		})
+		.map(|(i, c)| i + c.len_utf8());
+
+	if let Some(end) = first_sentence_end {
+		return text[..end].to_string();
+	}
+
+	let cut: String = text.chars().take(SYNTHETIC_TITLE_LENGTH).collect();
+	let cut = match cut.rfind(' ') {
+		Some(last_space) if last_space > 0 => &cut[..last_space],
+		_ => &cut
+	};
+	format!("{}…", cut.trim_end_matches(|c: char| c.is_ascii_punctuation()))
+}
+
+///Removes anything a terminal would interpret instead of print.
+///
+///That is escape sequences (colors, cursor movement, window titles, OSC 8 hyperlinks...),
+///all other control characters and the unicode bidi overrides that can hide or reorder text.
+///Whitespace controls (newlines, tabs) become spaces.
+pub fn terminal_safe(source: &str) -> String {
+	let mut output = String::with_capacity(source.len());
+	let mut chars = source.chars().peekable();
+
+	while let Some(c) = chars.next() {
+		match c {
+			'\u{1b}' => match chars.next() {
+				//CSI: parameters and intermediates until a final byte in @..~
+				Some('[') => {
+					for c in chars.by_ref() {
+						if ('@'..='~').contains(&c) { break; }
+					}
+				},
+				//OSC, DCS, SOS, PM, APC: strings ended by BEL or ST (ESC \)
+				Some(']' | 'P' | 'X' | '^' | '_') => {
+					while let Some(c) = chars.next() {
+						if c == '\u{7}' { break; }
+						if c == '\u{1b}' && chars.peek() == Some(&'\\') {
+							chars.next();
+							break;
+						}
+					}
+				},
+				//any other escape is ESC plus a single character
+				_ => {}
+			},
+			'\t' | '\n' | '\r' => output.push(' '),
+			//C1 controls, bidi embeddings/overrides and isolates
+			c if c.is_control() => {},
+			'\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' => {},
+			c => output.push(c)
+		}
+	}
+
+	output
+}
+
+///Tags that separate words when rendered, they get replaced by a space.
+const BLOCK_TAGS: &[&str] = &[
+	"address", "article", "aside", "blockquote", "br", "dd", "div", "dl", "dt", "figcaption",
+	"figure", "footer", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "img", "li", "ol",
+	"p", "pre", "section", "table", "td", "th", "tr", "ul"
+];
+
+///Drops everything between < and >, along with the contents of script and style blocks.
+///Block tags are replaced by a space so that `<p>a</p><p>b</p>` doesn't end up as "ab",
+///inline ones (`<em>`, `<a>`...) just disappear.
+fn strip_tags(source: &str) -> String {
+	let mut output = String::with_capacity(source.len());
+	let mut rest = source;
+
+	while let Some(start) = rest.find('<') {
+		output.push_str(&rest[..start]);
+		let tag = &rest[start..];
+
+		//a lone '<' (say "a < b") is not a tag
+		let Some(end) = tag.find('>') else {
+			output.push_str(tag);
+			return output;
+		};
+
+		let tag_name = tag[1..end].trim_start_matches('/')
+			.split(|c: char| c.is_whitespace() || c == '/')
+			.next()
+			.unwrap_or("")
+			.to_ascii_lowercase();
+
+		rest = if tag.starts_with("<!--") {
+			tag.find("-->").map_or("", |close| &tag[close + 3..])
+		} else if !tag[1..].starts_with('/') && (tag_name == "script" || tag_name == "style") {
+			let closing = format!("</{tag_name}");
+			tag.to_ascii_lowercase().find(&closing)
+				.and_then(|close| tag[close..].find('>').map(|gt| &tag[close + gt + 1..]))
+				.unwrap_or("")
+		} else {
+			&tag[end + 1..]
+		};
+		if BLOCK_TAGS.contains(&tag_name.as_str()) {
+			output.push(' ');
+		}
+	}
+
+	output.push_str(rest);
+	output
+}
+
+///Decodes the most common named entities and all numeric ones.
+///Unknown entities are left untouched.
+fn decode_entities(source: &str) -> String {
+	let mut output = String::with_capacity(source.len());
+	let mut rest = source;
+
+	while let Some(start) = rest.find('&') {
+		output.push_str(&rest[..start]);
+		let entity = &rest[start..];
+
+		//entities are short, if there is no ';' close by this is just an ampersand
+		let decoded = entity.char_indices()
+			.take(12)
+			.find(|&(_, c)| c == ';')
+			.and_then(|(end, _)| decode_entity(&entity[1..end]).map(|c| (c, end)));
+
+		match decoded {
+			Some((c, end)) => {
+				output.push(c);
+				rest = &entity[end + 1..];
+			},
+			None => {
+				output.push('&');
+				rest = &entity[1..];
+			}
+		}
+	}
+
+	output.push_str(rest);
+	output
+}
+
+///Character for an entity name (what goes between & and ;)
+fn decode_entity(name: &str) -> Option<char> {
+	if let Some(number) = name.strip_prefix('#') {
+		let code = match number.strip_prefix(['x', 'X']) {
+			Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+			None => number.parse::<u32>().ok()?
+		};
+		return char::from_u32(code);
+	}
+
+	let c = match name {
+		"amp" => '&',
+		"lt" => '<',
+		"gt" => '>',
+		"quot" => '"',
+		"apos" => '\'',
+		"nbsp" => ' ',
+		"ndash" => '–',
+		"mdash" => '—',
+		"hellip" => '…',
+		"lsquo" => '‘',
+		"rsquo" => '’',
+		"ldquo" => '“',
+		"rdquo" => '”',
+		"laquo" => '«',
+		"raquo" => '»',
+		"copy" => '©',
+		"reg" => '®',
+		"trade" => '™',
+		"middot" => '·',
+		"bull" => '•',
+		_ => return None
+	};
+	Some(c)
+}
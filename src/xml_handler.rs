@@ -1,14 +1,15 @@
 //! Parsing the RSS XML into structs we can handle
 
-use chrono::{DateTime, offset::Utc};
-use roxmltree::Node;
+use chrono::{DateTime, FixedOffset, ParseResult, offset::Utc};
+use roxmltree::{Node, TextPos};
 use std::{fmt, error};
 use crate::rss::{Channel, Item};
 
 #[derive(Debug)]
 pub enum XmlError {
 	UnknownFormat,
-	ParserFailed,
+	///roxmltree could not read the document, keeps the parser error (and with it the position)
+	ParserFailed(roxmltree::Error),
 	NoChannelTag,
 	NoTitle,
 	NoLink
@@ -17,7 +18,7 @@ impl fmt::Display for XmlError {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		let err_string = match self {
 			XmlError::UnknownFormat => "Format of the XML passed is neither Atom nor RSS",
-			XmlError::ParserFailed => "Could not parse XML",
+			XmlError::ParserFailed(_) => "Could not parse XML",
 			XmlError::NoChannelTag => "The channel tag was not found in the xml passed",
 			XmlError::NoTitle => "The title for the channel is not present in the xml passed",
 			XmlError::NoLink => "The link for the channel is not present in the xml passed",
@@ -28,10 +29,103 @@ impl fmt::Display for XmlError {
 }
 impl error::Error for XmlError {
 	fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-		None
+		match self {
+			XmlError::ParserFailed(e) => Some(e),
+			_ => None
+		}
 	}
 }
 
+///The kinds of feed we know how to read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+	Rss,
+	Atom
+}
+impl fmt::Display for FeedFormat {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			FeedFormat::Rss => write!(f, "RSS"),
+			FeedFormat::Atom => write!(f, "Atom")
+		}
+	}
+}
+
+///A non fatal problem found while parsing a feed.
+///
+///The parser recovers from all of these (by skipping the item or ignoring the field),
+///they are only collected so that _validate_ can report them.
+#[derive(Debug)]
+pub enum Diagnostic {
+	///An item/entry was dropped, it has nothing we can use as a title
+	SkippedItem { pos: TextPos },
+	///A date tag was present but its contents could not be parsed
+	UnparseableDate { pos: TextPos, tag: String, value: String },
+	///A field that the spec requires or recommends is not there
+	MissingField { pos: TextPos, parent: String, field: String }
+}
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Diagnostic::SkippedItem { pos } =>
+				write!(f, "{pos}: item skipped, it has neither a title nor a description"),
+			Diagnostic::UnparseableDate { pos, tag, value } =>
+				write!(f, "{pos}: could not parse date \"{value}\" in <{tag}>"),
+			Diagnostic::MissingField { pos, parent, field } =>
+				write!(f, "{pos}: <{parent}> is missing the recommended field <{field}>")
+		}
+	}
+}
+
+///Everything we learned about a feed while parsing it, besides the channel itself.
+#[derive(Debug, Default)]
+pub struct Report {
+	///Format of the feed, None if we could not tell (or could not parse the XML at all)
+	pub format: Option<FeedFormat>,
+	pub diagnostics: Vec<Diagnostic>
+}
+
+///Position (row and column) where a node starts in its document
+fn position(node: &Node) -> TextPos {
+	node.document().text_pos_at(node.position())
+}
+
+///Parse the text of a date tag, noting in the report if it is present but malformed.
+fn get_date_from_child(
+	child: Option<Node>,
+	parse: fn(&str) -> ParseResult<DateTime<FixedOffset>>,
+	report: &mut Report
+) -> Option<DateTime<Utc>> {
+	let child = child?;
+	let date_s = child.text()?;
+
+	match parse(date_s.trim()) {
+		Ok(fixed_date) => Some(DateTime::<Utc>::from(fixed_date)),
+		Err(_) => {
+			report.diagnostics.push(Diagnostic::UnparseableDate {
+				pos: position(&child),
+				tag: child.tag_name().name().to_string(),
+				value: date_s.to_string()
+			});
+			None
+		}
+	}
+}
+
+///Note in the report that _parent_ lacks _field_ if the child was not found
+fn check_recommended(parent: &Node, child: Option<Node>, field: &str, report: &mut Report) {
+	if child.is_none() {
+		report.diagnostics.push(Diagnostic::MissingField {
+			pos: position(parent),
+			parent: parent.tag_name().name().to_string(),
+			field: field.to_string()
+		});
+	}
+}
+
+///Namespace of the RSS content module, that gives items their full text (content:encoded)
+const RSS_CONTENT_NAMESPACE: &str = "http://purl.org/rss/1.0/modules/content/";
+
 /// Tries to find a direct child of the parent tag with the name _name_
 fn get_named_child<'a>(parent: &Node<'a, 'a>, name: &str) -> Option<Node<'a, 'a>> {
 	//Maybe we should be normalizing the strings before comparing them
@@ -47,21 +141,41 @@ fn get_text_from_child(parent: &Node, name: &str) -> Option<String> {
 	Some(String::from(borrowed))
 }
 
-fn process_item(item_tag: &Node) -> Option<Item> {
-	let title_or_description:String = get_text_from_child(item_tag, "title")
-		.or(get_text_from_child(item_tag, "description"))?;
+fn process_item(item_tag: &Node, report: &mut Report) -> Option<Item> {
+	let title = get_text_from_child(item_tag, "title");
+	let title_is_missing = title.is_none();
+	let description = get_text_from_child(item_tag, "description");
+	//when there's no title the description takes its place (and is still kept as the description)
+	let (title_or_description, description) = match (title, description) {
+		(Some(title), description) => (title, description),
+		(None, Some(description)) => (description.clone(), Some(description)),
+		(None, None) => {
+			report.diagnostics.push(Diagnostic::SkippedItem { pos: position(item_tag) });
+			return None;
+		}
+	};
 
 	let link:Option<_> = get_text_from_child(item_tag, "link");
+	check_recommended(item_tag, get_named_child(item_tag, "link"), "link", report);
+
+	let pub_date_tag = get_named_child(item_tag, "pubDate");
+	check_recommended(item_tag, pub_date_tag, "pubDate", report);
+	let pub_date:Option<DateTime<Utc>> = get_date_from_child(pub_date_tag, DateTime::parse_from_rfc2822, report);
 
-	let pub_date:Option<DateTime<Utc>> = get_text_from_child(item_tag, "pubDate")
-		.and_then(|date_s| DateTime::parse_from_rfc2822(&date_s).ok())
-		.and_then(|fixed_date| Some(DateTime::<Utc>::from(fixed_date)));
+	let content = item_tag.children()
+		.find(|c| c.tag_name().name() == "encoded" && c.tag_name().namespace() == Some(RSS_CONTENT_NAMESPACE))
+		.and_then(|c| c.text())
+		.map(String::from);
 
-	Some(Item::new(title_or_description, link, pub_date))
+	let mut item = Item::new(title_or_description, link, pub_date);
+	item.untitled = title_is_missing;
+	item.description = description;
+	item.content = content;
+	Some(item)
 }
 
 /// Parses an XML in RSS format into a Channel.
-fn parse_rss(root: Node) -> Result<Channel, XmlError> {
+fn parse_rss(root: Node, report: &mut Report) -> Result<Channel, XmlError> {
 	let channel_tag = get_named_child(&root, "channel")
 		.ok_or(XmlError::NoChannelTag)?;
 
@@ -74,14 +188,17 @@ fn parse_rss(root: Node) -> Result<Channel, XmlError> {
 	//This adds the disadvantage that some errors might go ignored
 	let description = get_text_from_child(&channel_tag, "description")
 		.unwrap_or(String::new());
+	check_recommended(&channel_tag, get_named_child(&channel_tag, "description"), "description", report);
 
-	let last_build_date:Option<DateTime<_>> = get_text_from_child(&channel_tag, "lastBuildDate")
-		.and_then(|date_s| DateTime::parse_from_rfc2822(&date_s).ok())
-		.and_then(|fixed_date| Some(DateTime::<Utc>::from(fixed_date)));
+	let last_build_date:Option<DateTime<_>> = get_date_from_child(
+		get_named_child(&channel_tag, "lastBuildDate"),
+		DateTime::parse_from_rfc2822,
+		report
+	);
 
 	let items:Vec<Item> = channel_tag .children()
 		.filter(|c| c.tag_name().name() == "item")
-		.flat_map(|i| process_item(&i))
+		.flat_map(|i| process_item(&i, report))
 		.collect();
 
 	Ok(Channel {
@@ -110,25 +227,33 @@ fn get_text_from_child_atom(parent: &Node, name: &str) -> Option<String> {
 }
 
 /// Parses a single _entry_ block in an atom feed
-fn process_atom_entry(entry: &Node) -> Option<Item> {
+fn process_atom_entry(entry: &Node, report: &mut Report) -> Option<Item> {
 	// Atom requires entries to have a title, no need to search for a description
 	// if one is not present
-	let title = get_text_from_child_atom(entry, "title")?;
+	let Some(title) = get_text_from_child_atom(entry, "title") else {
+		report.diagnostics.push(Diagnostic::SkippedItem { pos: position(entry) });
+		return None;
+	};
 
-	let link = get_named_child_atom(entry, "link")
+	let link_tag = get_named_child_atom(entry, "link");
+	check_recommended(entry, link_tag, "link", report);
+	let link = link_tag
 		.and_then(|link_tag| link_tag.attribute("href"))
 		.and_then(|href| Some(href.to_string()) );
 
 	// This is less strict than the atom spec, since updated is necessary.
-	let pub_date = get_text_from_child_atom(entry, "updated")
-		.and_then(|date_s| DateTime::parse_from_rfc3339(&date_s).ok())
-		.and_then(|fixed_date| Some(DateTime::<Utc>::from(fixed_date)));
+	let updated_tag = get_named_child_atom(entry, "updated");
+	check_recommended(entry, updated_tag, "updated", report);
+	let pub_date = get_date_from_child(updated_tag, DateTime::parse_from_rfc3339, report);
 
-	Some(Item::new(title, link, pub_date))
+	let mut item = Item::new(title, link, pub_date);
+	item.description = get_text_from_child_atom(entry, "summary");
+	item.content = get_text_from_child_atom(entry, "content");
+	Some(item)
 }
 
 /// Parses an XML in Atom format into a Channel.
-fn parse_atom(root: Node) -> Result<Channel, XmlError> {
+fn parse_atom(root: Node, report: &mut Report) -> Result<Channel, XmlError> {
 	let title = get_text_from_child_atom(&root, "title")
 		.ok_or(XmlError::NoTitle)?;
 
@@ -149,13 +274,13 @@ fn parse_atom(root: Node) -> Result<Channel, XmlError> {
 
 	//Not absolutely confident that **all** viable strings (xml:xsd) will be
 	//correctly parsed by this.
-	let last_build_date:Option<DateTime<_>> = get_text_from_child_atom(&root, "updated")
-		.and_then(|date_s| DateTime::parse_from_rfc3339(&date_s).ok())
-		.and_then(|fixed_date| Some(DateTime::<Utc>::from(fixed_date)));
+	let updated_tag = get_named_child_atom(&root, "updated");
+	check_recommended(&root, updated_tag, "updated", report);
+	let last_build_date:Option<DateTime<_>> = get_date_from_child(updated_tag, DateTime::parse_from_rfc3339, report);
 
 	let items:Vec<Item> = root.children()
 		.filter(|c| c.tag_name().name() == "entry")
-		.flat_map(|i| process_atom_entry(&i))
+		.flat_map(|i| process_atom_entry(&i, report))
 		.collect();
 
 	Ok(Channel {
@@ -171,13 +296,31 @@ fn parse_atom(root: Node) -> Result<Channel, XmlError> {
 /// Works for both RSS & Atom, though most of Atom's features are ignored.
 /// If the xml is malformed/unparsable, an error is returned.
 pub fn xml_to_rss(xml_source: &str) -> Result<Channel, XmlError> {
-	let xml_tree = roxmltree::Document::parse(xml_source)
-		.map_err(|_| XmlError::ParserFailed )?;
+	xml_to_rss_with_report(xml_source).0
+}
+
+/// Same as xml_to_rss, but also returns what was found out about the feed while parsing
+/// (format, skipped items, bad dates...). The report is filled even if parsing fails.
+pub fn xml_to_rss_with_report(xml_source: &str) -> (Result<Channel, XmlError>, Report) {
+	let mut report = Report::default();
+
+	let xml_tree = match roxmltree::Document::parse(xml_source) {
+		Ok(tree) => tree,
+		Err(e) => return (Err(XmlError::ParserFailed(e)), report)
+	};
 	let root = xml_tree.root_element();
 
-	match root.tag_name().namespace() {
-		Some("http://www.w3.org/2005/Atom") => parse_atom(root),
-		None if root.tag_name().name() == "rss" => parse_rss(root),
+	let channel = match root.tag_name().namespace() {
+		Some("http://www.w3.org/2005/Atom") => {
+			report.format = Some(FeedFormat::Atom);
+			parse_atom(root, &mut report)
+		},
+		None if root.tag_name().name() == "rss" => {
+			report.format = Some(FeedFormat::Rss);
+			parse_rss(root, &mut report)
+		},
 		_ => Err(XmlError::UnknownFormat)
-	}
+	};
+
+	(channel, report)
 }
@@ -0,0 +1,91 @@
+use std::{fs, path::Path, str::from_utf8};
+
+use rusqlite::Connection;
+
+mod utils;
+use utils::*;
+
+///How many items `corkboard new` lists
+fn unread_count() -> usize {
+	let output = run_cork(&["new"]);
+	assert!(output.status.success());
+	from_utf8(&output.stdout).unwrap().lines().count()
+}
+
+///Names of the snapshots taken so far
+fn snapshots() -> Vec<String> {
+	fs::read_dir("./corkdb.snapshots").unwrap()
+		.map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+		.collect()
+}
+
+///Delete the backup, along with the WAL files opening it leaves behind
+fn remove_backup() {
+	for file in ["./backup-test.corkdb", "./backup-test.corkdb-wal", "./backup-test.corkdb-shm"] {
+		let path = Path::new(file);
+		if path.exists() {
+			fs::remove_file(path).unwrap();
+		}
+	}
+}
+
+#[test]
+fn backup_test() {
+	ensure_new_database();
+	let backup = Path::new("./backup-test.corkdb");
+	remove_backup();
+
+	let _feed = Miniserve::launch("./assets/search.rss", None);
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+	assert!(run_cork(&["mark", "1"]).status.success());
+	assert_eq!(unread_count(), 2);
+
+	//A backup is a working copy of the database
+	assert!(run_cork(&["backup", "./backup-test.corkdb"]).status.success());
+	{
+		let copy = Connection::open(backup).unwrap();
+		assert_eq!(count_items(&copy), 3);
+		let read: i64 = copy.query_row("SELECT COUNT(*) FROM items WHERE read = 1;", [], |row| row.get(0)).unwrap();
+		assert_eq!(read, 1);
+	}
+	//and can be written again
+	assert!(run_cork(&["backup", "./backup-test.corkdb"]).status.success());
+
+	//Marking everything takes a snapshot first
+	assert!(run_cork(&["mark", "--all"]).status.success());
+	assert_eq!(unread_count(), 0);
+	let taken = snapshots();
+	assert_eq!(taken.len(), 1);
+	assert!(taken[0].ends_with("-mark-all.corkdb"));
+
+	//Restoring brings the read state back, and snapshots the database it replaces
+	let restore = run_cork(&["restore", "./backup-test.corkdb"]);
+	assert!(restore.status.success());
+	assert!(from_utf8(&restore.stdout).unwrap().contains("corkdb.snapshots"));
+	assert_eq!(unread_count(), 2);
+	assert!(snapshots().iter().any(|s| s.ends_with("-restore.corkdb")));
+
+	//Files that aren't usable corkboard databases are refused, leaving the database alone
+	assert!(!run_cork(&["restore", "./assets/search.rss"]).status.success());
+	assert!(!run_cork(&["restore", "./no-such-backup.corkdb"]).status.success());
+	{
+		let copy = Connection::open(backup).unwrap();
+		copy.pragma_update(None, "user_version", 9999).unwrap();
+	}
+	assert!(!run_cork(&["restore", "./backup-test.corkdb"]).status.success());
+	{
+		let copy = Connection::open(backup).unwrap();
+		copy.pragma_update(None, "user_version", 0).unwrap();
+		copy.execute_batch("DROP TABLE quickmarks;").unwrap();
+	}
+	assert!(!run_cork(&["restore", "./backup-test.corkdb"]).status.success());
+	assert_eq!(unread_count(), 2);
+
+	//Only the newest snapshots are kept
+	for _ in 0..12 {
+		assert!(run_cork(&["mark", "--all"]).status.success());
+	}
+	assert_eq!(snapshots().len(), 10);
+
+	remove_backup();
+}
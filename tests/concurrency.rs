@@ -0,0 +1,47 @@
+use std::{
+	fs::File,
+	str::from_utf8,
+	thread,
+	time::Duration
+};
+
+use rusqlite::Connection;
+
+mod utils;
+use utils::*;
+
+#[test]
+fn concurrency() {
+	ensure_new_database();
+
+	let _feed = Miniserve::launch("./assets/sample3.rss", None);
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+
+	let db = Connection::open("corkdb").unwrap();
+	let journal_mode: String = db.query_row("PRAGMA journal_mode;", [], |row| row.get(0)).unwrap();
+	assert_eq!(journal_mode, "wal");
+
+	//A second update refuses to run while the first holds the lock
+	let lock = File::create("corkdb.lock").unwrap();
+	lock.try_lock().unwrap();
+	let blocked = run_cork(&["up"]);
+	assert!(!blocked.status.success());
+	assert!(from_utf8(&blocked.stderr).unwrap().contains("already running"));
+	lock.unlock().unwrap();
+	assert!(run_cork(&["up"]).status.success());
+
+	//Writers wait for each other instead of failing with "database is locked"
+	let writer = thread::spawn(|| {
+		let other = Connection::open("corkdb").unwrap();
+		other.execute_batch("BEGIN IMMEDIATE;").unwrap();
+		thread::sleep(Duration::from_secs(2));
+		other.execute_batch("COMMIT;").unwrap();
+	});
+	thread::sleep(Duration::from_millis(300));
+	let mark_output = run_cork(&["mark", "1"]);
+	assert!(mark_output.status.success(), "{}", from_utf8(&mark_output.stderr).unwrap());
+	writer.join().unwrap();
+
+	let read: i64 = db.query_row("SELECT COUNT(*) FROM items WHERE read = 1;", [], |row| row.get(0)).unwrap();
+	assert_eq!(read, 1);
+}
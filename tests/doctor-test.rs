@@ -0,0 +1,53 @@
+use std::str::from_utf8;
+
+use rusqlite::Connection;
+
+mod utils;
+use utils::*;
+
+#[test]
+fn doctor_test() {
+	ensure_new_database();
+
+	let _feed = Miniserve::launch("./assets/sample1.rss", None);
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+
+	//remove takes the items and quickmarks of the feed with it
+	assert!(run_cork(&["remove", "http://localhost:8080"]).status.success());
+	let db = Connection::open("corkdb").unwrap();
+	assert_eq!(count_items(&db), 0);
+	let quickmarks: i64 = db.query_row("SELECT COUNT(*) FROM quickmarks;", [], |row| row.get(0)).unwrap();
+	assert_eq!(quickmarks, 0);
+
+	//Databases from before foreign keys were enforced can hold orphaned rows,
+	//so stop enforcing them on our connection and make a mess
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+	db.execute_batch(
+		"PRAGMA foreign_keys=OFF;
+		DELETE FROM channels;
+		INSERT INTO quickmarks (position, hash) VALUES (17, 'nothing'), (18, 'nothing either');"
+	).unwrap();
+	assert_eq!(count_items(&db), 2);
+
+	//A dry run reports without touching anything
+	let dry_run = run_cork(&["doctor", "--dry-run"]);
+	assert!(dry_run.status.success());
+	let dry_output = from_utf8(&dry_run.stdout).expect("Could not read program output");
+	assert!(dry_output.contains("items whose channel no longer exists: 2"));
+	assert_eq!(count_items(&db), 2);
+
+	//A misspelled option is refused rather than taken for a repair
+	assert!(!run_cork(&["doctor", "--dryrun"]).status.success());
+	assert_eq!(count_items(&db), 2);
+
+	let repair = run_cork(&["doctor"]);
+	assert!(repair.status.success());
+	assert_eq!(count_items(&db), 0);
+	let quickmarks: i64 = db.query_row("SELECT COUNT(*) FROM quickmarks;", [], |row| row.get(0)).unwrap();
+	assert_eq!(quickmarks, 0);
+
+	//Nothing left to do
+	let second_repair = run_cork(&["doctor"]);
+	assert!(second_repair.status.success());
+	assert!(from_utf8(&second_repair.stdout).unwrap().contains("No problems found"));
+}
@@ -0,0 +1,86 @@
+use std::str::from_utf8;
+
+mod utils;
+use utils::*;
+
+#[cfg(not(feature = "encryption"))]
+#[test]
+fn encryption_not_built() {
+	ensure_new_database();
+
+	//A key is never silently ignored
+	let new_output = run_cork_with_env(&["new"], &[("CORKDB_KEY", "secret")]);
+	assert!(!new_output.status.success());
+	assert!(from_utf8(&new_output.stderr).unwrap().contains("without encryption support"));
+	assert!(!run_cork_with_env(&["encrypt"], &[("CORKDB_KEY", "secret")]).status.success());
+	assert!(!run_cork(&["encrypt"]).status.success());
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn encryption_test() {
+	use std::fs::{self, File};
+
+	let has_plaintext = |path: &std::path::Path| fs::read(path).unwrap().windows(6).any(|w| w == b"vacuum");
+	let snapshots = || fs::read_dir("./corkdb.snapshots").unwrap().map(|entry| entry.unwrap().path()).collect::<Vec<_>>();
+
+	ensure_new_database();
+	let key_file = "./encryption-test.key";
+	fs::write(key_file, "other key\n").unwrap();
+
+	let _feed = Miniserve::launch("./assets/search.rss", None);
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+	assert!(run_cork(&["mark", "1"]).status.success());
+	//a plaintext snapshot
+	assert!(run_cork(&["prune"]).status.success());
+	assert!(snapshots().iter().any(|s| has_plaintext(s)));
+
+	//Encrypting needs a key, then the database (and its snapshots) can't be read without it
+	assert!(!run_cork(&["encrypt"]).status.success());
+	assert!(run_cork_with_env(&["encrypt"], &[("CORKDB_KEY", "secret")]).status.success());
+	assert!(!has_plaintext(std::path::Path::new("./corkdb")));
+	assert!(!snapshots().is_empty());
+	assert!(!snapshots().iter().any(|s| has_plaintext(s)));
+	assert!(!run_cork(&["new"]).status.success());
+	assert!(!run_cork_with_env(&["new"], &[("CORKDB_KEY", "wrong")]).status.success());
+	let new_output = run_cork_with_env(&["new"], &[("CORKDB_KEY", "secret")]);
+	assert!(new_output.status.success());
+	assert_eq!(from_utf8(&new_output.stdout).unwrap().lines().count(), 2);
+	let search = run_cork_with_env(&["search", "vacuum"], &[("CORKDB_KEY", "secret")]);
+	assert_eq!(from_utf8(&search.stdout).unwrap().lines().count(), 2);
+
+	//Changing the key, the new one may come from a file
+	assert!(!run_cork_with_env(&["rekey"], &[("CORKDB_KEY", "secret")]).status.success());
+	//not while another corkboard has the database open
+	let in_use = File::open("./corkdb.in-use").unwrap();
+	in_use.lock_shared().unwrap();
+	let busy = run_cork_with_env(&["rekey"], &[("CORKDB_KEY", "secret"), ("CORKDB_NEW_KEY_FILE", key_file)]);
+	assert!(!busy.status.success());
+	assert!(from_utf8(&busy.stderr).unwrap().contains("corkdb.in-use"));
+	drop(in_use);
+	//a snapshot the current key can't open is deleted, the others get the new key
+	fs::write("./corkdb.snapshots/20000101T000000.000Z-unknown.corkdb", b"not under our key").unwrap();
+	let rekey = run_cork_with_env(&["rekey"], &[("CORKDB_KEY", "secret"), ("CORKDB_NEW_KEY_FILE", key_file)]);
+	assert!(rekey.status.success());
+	assert!(from_utf8(&rekey.stdout).unwrap().contains("Deleted 1 snapshots"));
+	assert!(!snapshots().iter().any(|s| s.to_string_lossy().contains("unknown")));
+	let restore_old = snapshots().into_iter().next().unwrap();
+	assert!(run_cork_with_env(&["restore", &restore_old.to_string_lossy()], &[("CORKDB_KEY_FILE", key_file)]).status.success());
+	assert!(!run_cork_with_env(&["new"], &[("CORKDB_KEY", "secret")]).status.success());
+	assert!(run_cork_with_env(&["new"], &[("CORKDB_KEY_FILE", key_file)]).status.success());
+	assert!(!run_cork_with_env(&["new"], &[("CORKDB_KEY", "other key"), ("CORKDB_KEY_FILE", key_file)]).status.success());
+
+	//Snapshots are encrypted with the key too
+	let with_key = [("CORKDB_KEY_FILE", key_file)];
+	assert!(run_cork_with_env(&["mark", "--all"], &with_key).status.success());
+	let snapshot = fs::read_dir("./corkdb.snapshots").unwrap()
+		.map(|entry| entry.unwrap().path())
+		.find(|path| path.to_string_lossy().ends_with("-mark-all.corkdb"))
+		.unwrap();
+	assert!(!fs::read(&snapshot).unwrap().windows(6).any(|w| w == b"vacuum"));
+	assert!(run_cork_with_env(&["restore", &snapshot.to_string_lossy()], &with_key).status.success());
+	let new_output = run_cork_with_env(&["new"], &with_key);
+	assert_eq!(from_utf8(&new_output.stdout).unwrap().lines().count(), 2);
+
+	fs::remove_file(key_file).unwrap();
+}
@@ -0,0 +1,65 @@
+use std::str::from_utf8;
+
+use rusqlite::Connection;
+use serde_json::Value;
+
+mod utils;
+use utils::*;
+
+#[test]
+fn expire_test() {
+	ensure_new_database();
+
+	//search.rss has three items, sample3.rss two
+	let _old = Miniserve::launch("./assets/search.rss", None);
+	let _other = Miniserve::launch("./assets/sample3.rss", Some(&["--port", "9090"]));
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+	assert!(run_cork(&["add", "http://localhost:9090"]).status.success());
+	let db = Connection::open("corkdb").unwrap();
+	db.execute("UPDATE items SET first_seen = 1677628800;", []).unwrap();
+
+	//Nothing expires without a policy
+	let up_output = run_cork(&["up"]);
+	assert!(up_output.status.success());
+	assert!(!from_utf8(&up_output.stderr).unwrap().contains("old unread items"));
+	assert_eq!(from_utf8(&run_cork(&["new"]).stdout).unwrap().lines().count(), 5);
+
+	//Old unread items are marked on up, except in feeds that opt out and the starred ones
+	assert!(run_cork(&["config", "expire_unread_days", "30"]).status.success());
+	assert!(run_cork(&["config", "expire_unread_days", "never", "--feed", "localhost:9090"]).status.success());
+	assert!(run_cork(&["star", "localhost/house"]).status.success());
+	let up_output = run_cork(&["up"]);
+	assert!(up_output.status.success());
+	assert!(from_utf8(&up_output.stderr).unwrap().contains("Marked 2 old unread items as read"));
+	let new_output = run_cork(&["new"]);
+	let new = from_utf8(&new_output.stdout).unwrap();
+	assert_eq!(new.lines().count(), 2);
+	assert!(!new.contains("localhost/vacuum") && !new.contains("localhost/indexes"));
+
+	//They are told apart from the items the user read
+	assert!(run_cork(&["mark", "1"]).status.success());
+	let history_output = run_cork(&["history", "--format", "json"]);
+	let history: Value = serde_json::from_slice(&history_output.stdout).unwrap();
+	let history = history.as_array().unwrap();
+	assert_eq!(history.len(), 3);
+	assert_eq!(history.iter().filter(|item| item["auto_read"] == true).count(), 2);
+
+	//An item unmarked by hand waits again from then on
+	assert!(run_cork(&["unmark", "localhost/vacuum"]).status.success());
+	let up_output = run_cork(&["up"]);
+	assert!(!from_utf8(&up_output.stderr).unwrap().contains("old unread items"));
+	assert!(from_utf8(&run_cork(&["new"]).stdout).unwrap().contains("localhost/vacuum"));
+	let auto_read: bool = db.query_row(
+		"SELECT auto_read FROM items WHERE url = 'http://localhost/vacuum';", [], |row| row.get(0)
+	).unwrap();
+	assert!(!auto_read);
+
+	//Updates aren't journaled, undo reverts the unmark rather than the expiring
+	db.execute("UPDATE items SET queued_at = NULL;", []).unwrap();
+	assert!(run_cork(&["up"]).status.success());
+	assert!(!from_utf8(&run_cork(&["new"]).stdout).unwrap().contains("localhost/vacuum"));
+	let undo_output = run_cork(&["undo"]);
+	assert!(undo_output.status.success());
+	assert!(from_utf8(&undo_output.stdout).unwrap().contains("Undid unmark link localhost/vacuum"));
+	assert!(!from_utf8(&run_cork(&["new"]).stdout).unwrap().contains("localhost/vacuum"));
+}
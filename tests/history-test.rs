@@ -0,0 +1,63 @@
+use std::str::from_utf8;
+
+use rusqlite::Connection;
+use serde_json::Value;
+
+mod utils;
+use utils::*;
+
+#[test]
+fn history_test() {
+	ensure_new_database();
+
+	let _dated = Miniserve::launch("./assets/search.rss", None);
+	let _undated = Miniserve::launch("./assets/sample3.rss", Some(&["--port", "9090"]));
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+	assert!(run_cork(&["add", "http://localhost:9090"]).status.success());
+	let db = Connection::open("corkdb").unwrap();
+
+	//Every item knows when it was first seen, none has been read yet
+	let unseen: i64 = db.query_row("SELECT COUNT(*) FROM items WHERE first_seen IS NULL;", [], |row| row.get(0)).unwrap();
+	assert_eq!(unseen, 0);
+	let history_output = run_cork(&["history"]);
+	assert!(history_output.status.success());
+	assert!(history_output.stdout.is_empty());
+
+	//Undated items are ordered by when they were first seen, after the dated ones here
+	let new_output = run_cork(&["new"]);
+	let new_lines: Vec<String> = from_utf8(&new_output.stdout).unwrap().lines().map(String::from).collect();
+	assert!(new_lines[0].contains("Postgres vacuum explained"));
+	assert!(new_lines[3..].iter().any(|l| l.contains("azz")));
+	assert!(new_lines[3..].iter().any(|l| l.contains("bzz")));
+
+	//Marking records the time, the most recently read come first
+	let azz = hash_string("azz");
+	assert!(run_cork(&["markhash", &azz]).status.success());
+	db.execute("UPDATE items SET read_at = 1677628800 WHERE hash = ?;", [&azz]).unwrap();
+	assert!(run_cork(&["mark", "1"]).status.success());
+
+	let history_output = run_cork(&["history"]);
+	let history: Vec<String> = from_utf8(&history_output.stdout).unwrap().lines().map(String::from).collect();
+	assert_eq!(history.len(), 2);
+	assert!(history[0].contains("Postgres vacuum explained"));
+	assert!(history[1].starts_with("2023-0"));
+	assert!(history[1].contains(&azz));
+
+	//Marking again keeps the first time
+	assert!(run_cork(&["markhash", &azz]).status.success());
+	let read_at: i64 = db.query_row("SELECT read_at FROM items WHERE hash = ?;", [&azz], |row| row.get(0)).unwrap();
+	assert_eq!(read_at, 1677628800);
+
+	//Filters
+	let recent = run_cork(&["history", "--since", "2024-01-01"]);
+	assert_eq!(from_utf8(&recent.stdout).unwrap().lines().count(), 1);
+	let old = run_cork(&["history", "--until", "2023-03-01", "--format", "json"]);
+	let old: Value = serde_json::from_slice(&old.stdout).expect("history did not print JSON");
+	assert_eq!(old.as_array().unwrap().len(), 1);
+	assert_eq!(old[0]["title_or_description"], "azz");
+	assert!(old[0]["read_at"].is_string());
+	let other_feed = run_cork(&["history", "--feed", "localhost:8080"]);
+	assert_eq!(from_utf8(&other_feed.stdout).unwrap().lines().count(), 1);
+
+	assert!(!run_cork(&["history", "--read"]).status.success());
+}
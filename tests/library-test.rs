@@ -0,0 +1,69 @@
+use std::{env, fs, net::TcpStream, process, thread, time::Duration};
+
+use corkboard::{Corkboard, db::ItemFilter, fetch::Limits, xml_to_rss};
+
+mod utils;
+use utils::*;
+
+///Wait until miniserve listens, running the program gave it the time in other tests
+fn wait_for_port(port: u16) {
+	for _ in 0..50 {
+		if TcpStream::connect(("localhost", port)).is_ok() {
+			return;
+		}
+		thread::sleep(Duration::from_millis(100));
+	}
+	panic!("Nothing is listening on port {port}");
+}
+
+///Same flow as the command line tests, through the library and with a database of its own
+#[test]
+fn library_test() {
+	//in a directory of its own, the files the library keeps next to the database go away with it
+	let dir = env::temp_dir().join(format!("corkboard-library-test-{}", process::id()));
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	let db_path = dir.join("corkdb");
+
+	let mut feed = Miniserve::launch("./assets/sample2.rss", None);
+	wait_for_port(8080);
+	let board = Corkboard::open(&db_path).unwrap()
+		.with_limits(Limits { max_items: 2, ..Limits::default() });
+
+	let subscription = board.subscribe("localhost:8080").unwrap();
+	assert_eq!(subscription.channel.link, "http://localhost:8080");
+	assert_eq!(subscription.new_items.len(), 2);
+	assert_eq!(subscription.notes.len(), 1);
+	assert_eq!(board.feeds().unwrap().len(), 1);
+
+	let unread = board.unread().unwrap();
+	assert_eq!(unread.len(), 2);
+	assert_eq!(unread[0].0, 1);
+	board.mark(&[unread[0].0 as usize]).unwrap();
+	assert_eq!(board.unread().unwrap().len(), 1);
+	assert_eq!(board.history(&ItemFilter::default()).unwrap().len(), 1);
+
+	//A newer version of the feed, with more room for items
+	feed.kill();
+	let _newer_feed = Miniserve::launch("./assets/sample2-next-week.rss", None);
+	thread::sleep(Duration::from_millis(200));
+	wait_for_port(8080);
+	let board = board.with_limits(Limits::default());
+	let refresh = board.refresh().unwrap();
+	assert!(refresh.failures.is_empty());
+	assert_eq!(refresh.updates.len(), 1);
+	assert!(refresh.updates[0].1.iter().any(|i| i.link.as_deref() == Some("http://unique")));
+
+	board.mark_all().unwrap();
+	assert!(board.unread().unwrap().is_empty());
+
+	board.remove("localhost:8080").unwrap();
+	assert!(board.feeds().unwrap().is_empty());
+
+	//The parser works on its own
+	let channel = xml_to_rss(&fs::read_to_string("./assets/sample3.rss").unwrap()).unwrap();
+	assert_eq!(channel.items.len(), 2);
+
+	drop(board);
+	fs::remove_dir_all(&dir).unwrap();
+}
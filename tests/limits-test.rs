@@ -0,0 +1,44 @@
+use std::str::from_utf8;
+
+use serde_json::Value;
+
+mod utils;
+use utils::*;
+
+#[test]
+fn limits_test() {
+	//A feed larger than the limit is refused
+	let too_big = run_cork_with_env(&["parse", "./assets/sample2.rss"], &[("CORKBOARD_MAX_FEED_BYTES", "100")]);
+	assert!(!too_big.status.success());
+	let errors = from_utf8(&too_big.stderr).expect("Could not read output as string");
+	assert!(errors.contains("CORKBOARD_MAX_FEED_BYTES"));
+
+	//Extra items are dropped and long titles cut
+	let cut = run_cork_with_env(&["parse", "./assets/sample2.rss"], &[
+		("CORKBOARD_MAX_ITEMS", "2"),
+		("CORKBOARD_MAX_FIELD_LENGTH", "4")
+	]);
+	assert!(cut.status.success());
+	let channel: Value = serde_json::from_slice(&cut.stdout).expect("parse did not print JSON");
+	let items = channel["items"].as_array().unwrap();
+	assert_eq!(items.len(), 1);
+	assert_eq!(items[0]["title_or_description"], "Test");
+	assert_eq!(items[0]["title_or_description_hash"], hash_string("Test").as_str());
+
+	//Limits must be numbers
+	let invalid = run_cork_with_env(&["parse", "./assets/sample2.rss"], &[("CORKBOARD_MAX_ITEMS", "lots")]);
+	assert!(!invalid.status.success());
+
+	//and none of them may be 0
+	for variable in ["CORKBOARD_MAX_FEED_BYTES", "CORKBOARD_MAX_ITEMS", "CORKBOARD_MAX_FIELD_LENGTH", "CORKBOARD_FETCH_TIMEOUT"] {
+		let zero = run_cork_with_env(&["parse", "./assets/sample2.rss"], &[(variable, "0")]);
+		assert!(!zero.status.success());
+		assert!(from_utf8(&zero.stderr).unwrap().contains(variable));
+	}
+
+	//Over the network too
+	ensure_new_database();
+	let _feed = Miniserve::launch("./assets/sample2.rss", None);
+	let add = run_cork_with_env(&["add", "http://localhost:8080"], &[("CORKBOARD_MAX_FEED_BYTES", "100")]);
+	assert!(!add.status.success());
+}
@@ -0,0 +1,80 @@
+use std::{fs, path::Path};
+
+use rusqlite::Connection;
+
+mod utils;
+use utils::*;
+
+#[test]
+fn migration_test() {
+	//A database from before schema versions, with a feed in it
+	ensure_new_database();
+	{
+		let db = Connection::open("corkdb").unwrap();
+		db.execute_batch(
+			"CREATE TABLE channels (
+				id INTEGER PRIMARY KEY AUTOINCREMENT,
+				title VARCHAR(256),
+				link VARCHAR(256) UNIQUE,
+				description TEXT,
+				last_build_date VARCHAR
+			);
+			CREATE TABLE items (
+				hash VARCHAR PRIMARY KEY,
+				title_or_desc VARCHAR(256) NOT NULL,
+				url VARCHAR(256),
+				pub_date VARCHAR,
+				read BOOLEAN NOT NULL,
+				channel INTEGER NOT NULL,
+				FOREIGN KEY(channel) REFERENCES channels(id) ON DELETE CASCADE
+			);
+			CREATE TABLE quickmarks (
+				position INTEGER,
+				hash VARCHAR,
+				FOREIGN KEY(hash) REFERENCES items(hash) ON DELETE CASCADE
+			);
+			INSERT INTO channels (title, link, description, last_build_date)
+				VALUES ('old', 'http://old.feed', '', '2023-03-01 10:00:00+00:00');
+			INSERT INTO items (hash, title_or_desc, read, channel) VALUES ('abc', 'old post', 0, 1);
+			INSERT INTO items (hash, title_or_desc, pub_date, read, channel)
+				VALUES ('def', 'dated post', '2023-01-02T12:00:00.5+00:00', 0, 1);
+			INSERT INTO quickmarks (position, hash) VALUES (1, 'abc');
+			INSERT INTO quickmarks (position, hash) VALUES (2, 'def');"
+		).unwrap();
+	}
+
+	//Any command brings it up to date, keeping the data and a snapshot of the old version
+	let feeds = run_cork(&["feeds"]);
+	assert!(feeds.status.success());
+	assert!(String::from_utf8_lossy(&feeds.stdout).contains("http://old.feed"));
+	let snapshots: Vec<String> = fs::read_dir("./corkdb.snapshots").unwrap()
+		.map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+		.collect();
+	assert_eq!(snapshots.len(), 1);
+	assert!(snapshots[0].ends_with("-v0.corkdb"));
+	let old: i64 = Connection::open(Path::new("./corkdb.snapshots").join(&snapshots[0])).unwrap()
+		.query_row("PRAGMA user_version;", [], |row| row.get(0)).unwrap();
+	assert_eq!(old, 0);
+
+	let db = Connection::open("corkdb").unwrap();
+	let version: i64 = db.query_row("PRAGMA user_version;", [], |row| row.get(0)).unwrap();
+	assert!(version >= 1);
+	assert_eq!(count_channels(&db), 1);
+	assert_eq!(count_items(&db), 2);
+
+	//Text dates became unix timestamps, items without any date go after the dated ones
+	let pub_date: i64 = db.query_row("SELECT pub_date FROM items WHERE hash = 'def';", [], |row| row.get(0)).unwrap();
+	assert_eq!(pub_date, 1672660800);
+	let build_date: i64 = db.query_row("SELECT last_build_date FROM channels;", [], |row| row.get(0)).unwrap();
+	assert_eq!(build_date, 1677664800);
+	let new_output = run_cork(&["new"]);
+	let new_lines = String::from_utf8_lossy(&new_output.stdout);
+	assert!(new_lines.contains("1 -> [No link] dated post"));
+	assert!(new_lines.contains("2 -> [No link] old post"));
+
+	//A database from the future is left alone
+	db.pragma_update(None, "user_version", 9999).unwrap();
+	assert!(!run_cork(&["feeds"]).status.success());
+	let version: i64 = db.query_row("PRAGMA user_version;", [], |row| row.get(0)).unwrap();
+	assert_eq!(version, 9999);
+}
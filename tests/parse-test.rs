@@ -0,0 +1,26 @@
+use serde_json::Value;
+
+mod utils;
+use utils::*;
+
+#[test]
+fn parse_test() {
+	//RSS and Atom should come out with the same shape
+	let rss_output = run_cork(&["parse", "./assets/sample3.rss"]);
+	assert!(rss_output.status.success());
+	let rss: Value = serde_json::from_slice(&rss_output.stdout).expect("parse did not print JSON");
+	assert_eq!(rss["title"], "Simpler feed");
+	assert_eq!(rss["items"].as_array().unwrap().len(), 2);
+	assert_eq!(rss["items"][0]["title_or_description"], "azz");
+	assert_eq!(rss["items"][0]["title_or_description_hash"], hash_string("azz").as_str());
+	assert_eq!(rss["items"][1]["link"], "http://doesnotwork.zzzzzzz");
+
+	let atom_output = run_cork(&["parse", "./assets/atom2.rss"]);
+	assert!(atom_output.status.success());
+	let atom: Value = serde_json::from_slice(&atom_output.stdout).expect("parse did not print JSON");
+	assert_eq!(atom["link"], "http://localhost");
+	assert_eq!(atom["items"][1]["link"], "http://localhost/second");
+
+	//Something that is not a feed is an error
+	assert!(!run_cork(&["parse", "./assets/broken.rss"]).status.success());
+}
@@ -0,0 +1,38 @@
+use std::str::from_utf8;
+use rusqlite::Connection;
+
+mod utils;
+use utils::*;
+
+#[test]
+fn plain_text_titles() {
+	ensure_new_database();
+
+	let _feed = Miniserve::launch("./assets/html-descriptions.rss", None);
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+
+	let new_result = run_cork(&["new"]);
+	assert!(new_result.status.success());
+	let new_output = from_utf8(&new_result.stdout).expect("Could not read program output");
+
+	//One line per item, no markup or entities left
+	assert_eq!(new_output.trim_end().lines().count(), 3);
+	assert!(!new_output.contains('<'));
+	assert!(!new_output.contains("&amp;"));
+	assert!(!new_output.contains("alert"));
+
+	//A description standing in for a title gets shortened to its first sentence
+	assert!(new_output.contains("Tom & Jerry return."));
+	assert!(!new_output.contains("second sentence"));
+	assert!(new_output.contains("A short note"));
+
+	//Real titles are never shortened, however long
+	assert!(new_output.contains("Dr. Seuss and the surprisingly long history of the books that taught generations to read"));
+
+	//The description standing in for a title is still stored as the description
+	let db = Connection::open("corkdb").unwrap();
+	let descriptions: i64 = db.query_row(
+		"SELECT COUNT(*) FROM items WHERE untitled = 1 AND description = title_or_desc;", [], |row| row.get(0)
+	).unwrap();
+	assert_eq!(descriptions, 2);
+}
@@ -0,0 +1,80 @@
+use std::str::from_utf8;
+
+use rusqlite::Connection;
+
+mod utils;
+use utils::*;
+
+///Count the items of a feed still in the database
+fn items_from(db: &Connection, link: &str) -> i64 {
+	db.query_row(
+		"SELECT COUNT(*) FROM items INNER JOIN channels ON channels.id = items.channel WHERE link = ?;",
+		[link], |row| row.get(0)
+	).unwrap()
+}
+
+#[test]
+fn prune_test() {
+	ensure_new_database();
+
+	//search.rss has three dated items (all from 2023), sample3.rss two undated ones
+	let _dated = Miniserve::launch("./assets/search.rss", None);
+	let _undated = Miniserve::launch("./assets/sample3.rss", Some(&["--port", "9090"]));
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+	assert!(run_cork(&["add", "http://localhost:9090"]).status.success());
+	let db = Connection::open("corkdb").unwrap();
+
+	//Nothing is pruned without a policy
+	assert!(run_cork(&["mark", "1", "2", "3"]).status.success());
+	assert!(run_cork(&["prune"]).status.success());
+	assert_eq!(count_items(&db), 5);
+
+	//Only read items go, the newest ones are kept
+	assert!(run_cork(&["config", "keep_items", "0"]).status.success());
+	assert!(run_cork(&["config", "keep_items", "1", "--feed", "localhost:8080"]).status.success());
+	let prune_output = run_cork(&["prune"]);
+	assert!(from_utf8(&prune_output.stdout).unwrap().contains("Pruned 2 items"));
+	assert_eq!(items_from(&db, "http://localhost:8080"), 1);
+	assert_eq!(items_from(&db, "http://localhost:9090"), 2);
+	let kept: String = db.query_row("SELECT title_or_desc FROM items WHERE read = 1;", [], |row| row.get(0)).unwrap();
+	assert_eq!(kept, "Cleaning the house");
+
+	//Pruned items are not resurrected by up
+	assert!(run_cork(&["up"]).status.success());
+	assert_eq!(items_from(&db, "http://localhost:8080"), 1);
+	let new_output = run_cork(&["new"]);
+	assert!(!from_utf8(&new_output.stdout).unwrap().contains("Postgres"));
+
+	//Age limits (counted from when the items were read), a feed can opt out of a global limit
+	db.execute("UPDATE items SET read_at = 1677628800 WHERE read = 1;", []).unwrap();
+	assert!(run_cork(&["config", "keep_items", "--unset"]).status.success());
+	assert!(run_cork(&["config", "keep_read_days", "30"]).status.success());
+	assert!(run_cork(&["config", "keep_read_days", "never", "--feed", "http://localhost:8080"]).status.success());
+	assert!(run_cork(&["prune"]).status.success());
+	assert_eq!(items_from(&db, "http://localhost:8080"), 1);
+	assert!(run_cork(&["config", "keep_read_days", "--unset", "--feed", "localhost:8080"]).status.success());
+
+	let config_output = run_cork(&["config"]);
+	let config = from_utf8(&config_output.stdout).unwrap();
+	assert!(config.contains("keep_read_days = 30"));
+	assert!(config.contains("keep_items is not set"));
+	assert!(config.contains("http://localhost:8080: keep_items = 1"));
+
+	//Pruning after up
+	assert!(run_cork(&["config", "prune_after_up", "true"]).status.success());
+	assert!(run_cork(&["up"]).status.success());
+	assert_eq!(items_from(&db, "http://localhost:8080"), 0);
+	let tombstones: i64 = db.query_row("SELECT COUNT(*) FROM tombstones;", [], |row| row.get(0)).unwrap();
+	assert_eq!(tombstones, 3);
+
+	//Removing a feed takes its tombstones along
+	assert!(run_cork(&["remove", "http://localhost:8080"]).status.success());
+	let tombstones: i64 = db.query_row("SELECT COUNT(*) FROM tombstones;", [], |row| row.get(0)).unwrap();
+	assert_eq!(tombstones, 0);
+
+	//Bad settings
+	assert!(!run_cork(&["config", "keep_forever", "1"]).status.success());
+	assert!(!run_cork(&["config", "keep_items", "-3"]).status.success());
+	assert!(!run_cork(&["config", "keep_items", "3", "--feed", "localhost:7070"]).status.success());
+	assert!(!run_cork(&["config", "prune_after_up", "true", "--feed", "localhost:9090"]).status.success());
+}
@@ -0,0 +1,62 @@
+use serde_json::Value;
+
+mod utils;
+use utils::*;
+
+#[test]
+fn search_test() {
+	ensure_new_database();
+
+	let _feed = Miniserve::launch("./assets/search.rss", None);
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+
+	//Titles, descriptions and contents are all searched
+	let vacuum = lines(&["search", "vacuum"]);
+	assert_eq!(vacuum.len(), 2);
+	assert!(vacuum.iter().any(|l| l.contains("Postgres vacuum explained")));
+	assert!(vacuum.iter().any(|l| l.contains("Cleaning the house")));
+	assert!(lines(&["search", "autovacuum"])[0].contains("Postgres vacuum explained"));
+	assert!(lines(&["search", "plan"])[0].contains("Notes on indexes"));
+
+	//Phrases have to appear as written, plain words anywhere
+	assert_eq!(lines(&["search", "\"vacuum explained\""]).len(), 1);
+	assert_eq!(lines(&["search", "\"explained vacuum\""]).len(), 0);
+	assert_eq!(lines(&["search", "explained"]).len(), 2);
+
+	//Read items are still found, and the filters tell them apart
+	let vacuum_hash = hash_strings(&["Postgres vacuum explained", "http://localhost/vacuum"]);
+	assert!(run_cork(&["markhash", &vacuum_hash]).status.success());
+	let read = lines(&["search", "vacuum", "--read"]);
+	assert_eq!(read.len(), 1);
+	assert!(read[0].starts_with(&vacuum_hash));
+	let unread = lines(&["search", "vacuum", "--unread"]);
+	assert_eq!(unread.len(), 1);
+	assert!(unread[0].contains("Cleaning the house"));
+
+	//Feeds, with or without the protocol
+	assert_eq!(lines(&["search", "vacuum", "--feed", "localhost:8080"]).len(), 2);
+	assert_eq!(lines(&["search", "vacuum", "--feed", "http://localhost:9090"]).len(), 0);
+
+	//Dates, the end of a range includes the whole day
+	assert_eq!(lines(&["search", "vacuum", "--since", "2023-01-03"]).len(), 1);
+	assert_eq!(lines(&["search", "vacuum", "--until", "2023-01-02"]).len(), 1);
+	assert_eq!(lines(&["search", "vacuum", "--since", "2023-01-01", "--until", "2023-01-31"]).len(), 1);
+	assert_eq!(lines(&["search", "vacuum", "--since", "2023-03-01T00:00:00Z"]).len(), 0);
+
+	//JSON, the same as new
+	let json_output = run_cork(&["search", "vacuum", "--unread", "--format", "json"]);
+	let json: Value = serde_json::from_slice(&json_output.stdout).expect("search did not print JSON");
+	assert_eq!(json[0]["title_or_description"], "Cleaning the house");
+	assert_eq!(json[0]["description"], "A vacuum cleaner review");
+	assert!(json[0]["quickmark"].is_number());
+
+	let new_output = run_cork(&["new", "--format", "json"]);
+	let new: Value = serde_json::from_slice(&new_output.stdout).expect("new did not print JSON");
+	assert_eq!(new.as_array().unwrap().len(), 2);
+	assert_eq!(new[0]["quickmark"], 1);
+
+	//Bad queries and options are errors, not empty results
+	assert!(!run_cork(&["search", "\"unclosed"]).status.success());
+	assert!(!run_cork(&["search", "vacuum", "--since", "yesterday"]).status.success());
+	assert!(!run_cork(&["search", "vacuum", "--format", "xml"]).status.success());
+}
@@ -0,0 +1,43 @@
+use std::str::from_utf8;
+
+use rusqlite::Connection;
+
+mod utils;
+use utils::*;
+
+///The same post in two feeds (a blog's main feed and a category feed)
+#[test]
+fn shared_items() {
+	ensure_new_database();
+
+	let _main_feed = Miniserve::launch("./assets/sample1.rss", None);
+	let _category_feed = Miniserve::launch("./assets/sample1-category.rss", Some(&["--port", "9090"]));
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+	assert!(run_cork(&["add", "http://localhost:9090"]).status.success());
+
+	let db = Connection::open("corkdb").unwrap();
+	assert_eq!(count_channels(&db), 2);
+	assert_eq!(count_items(&db), 4);
+
+	//The shared post shows up (and has a quickmark) once
+	let new_output = run_cork(&["new"]);
+	let new_text = from_utf8(&new_output.stdout).expect("Could not read program output");
+	assert_eq!(new_text.trim_end().lines().count(), 3);
+	assert_eq!(new_text.matches("http://localhost/zz").count(), 1);
+
+	//Reading it in one feed reads it in both
+	let shared_hash = hash_strings(&["An article", "http://localhost/zz"]);
+	assert!(run_cork(&["markhash", &shared_hash]).status.success());
+	let unread_copies: i64 = db.query_row(
+		"SELECT COUNT(*) FROM items WHERE hash=? AND read=0;", [&shared_hash], |row| row.get(0)
+	).unwrap();
+	assert_eq!(unread_copies, 0);
+
+	//Removing one feed keeps the other's copy
+	assert!(run_cork(&["remove", "http://localhost:9090"]).status.success());
+	assert_eq!(count_items(&db), 2);
+	let copies: i64 = db.query_row(
+		"SELECT COUNT(*) FROM items WHERE hash=?;", [&shared_hash], |row| row.get(0)
+	).unwrap();
+	assert_eq!(copies, 1);
+}
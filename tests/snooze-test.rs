@@ -0,0 +1,63 @@
+use std::str::from_utf8;
+
+use rusqlite::Connection;
+use serde_json::Value;
+
+mod utils;
+use utils::*;
+
+#[test]
+fn snooze_test() {
+	ensure_new_database();
+
+	let _feed = Miniserve::launch("./assets/search.rss", None);
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+	assert_eq!(lines(&["new"]), [
+		"1 -> [http://localhost/vacuum] Postgres vacuum explained",
+		"2 -> [http://localhost/indexes] Notes on indexes",
+		"3 -> [http://localhost/house] Cleaning the house"
+	]);
+	assert!(lines(&["snoozed"]).is_empty());
+
+	//Snoozed items leave the unread list, and are not marked with the rest
+	assert!(run_cork(&["snooze", "1", "3d"]).status.success());
+	assert_eq!(lines(&["new"]).len(), 2);
+	let snoozed = lines(&["snoozed"]);
+	assert_eq!(snoozed.len(), 1);
+	assert!(snoozed[0].contains("Postgres vacuum explained"));
+	assert!(run_cork(&["mark", "--all"]).status.success());
+	assert!(lines(&["new"]).is_empty());
+	let output = run_cork(&["snoozed", "--format", "json"]);
+	let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+	assert!(json[0]["read"] == false && json[0]["snoozed_until"].is_string());
+
+	//A snooze has to end in the future
+	let past = run_cork(&["snooze", "localhost/house", "2000-01-01"]);
+	assert!(!past.status.success());
+	assert!(from_utf8(&past.stderr).unwrap().contains("not in the future"));
+	assert!(!run_cork(&["snooze", "localhost/house", "0h"]).status.success());
+	assert_eq!(lines(&["snoozed"]).len(), 1);
+
+	//A snooze that is over puts the item at the front, a read item comes back unread
+	assert!(run_cork(&["unmark", "localhost/indexes"]).status.success());
+	assert!(run_cork(&["snooze", "localhost/house", "2999-01-01"]).status.success());
+	//as if the time had passed
+	let db = Connection::open("corkdb").unwrap();
+	db.execute("UPDATE items SET snoozed_until = 946684800 WHERE url = 'http://localhost/house';", []).unwrap();
+	assert_eq!(lines(&["new"]), [
+		"1 -> [http://localhost/house] Cleaning the house",
+		"2 -> [http://localhost/indexes] Notes on indexes"
+	]);
+	assert_eq!(lines(&["snoozed"]).len(), 1);
+
+	//Snoozing is undone like the rest
+	assert_eq!(lines(&["undo"]), ["Undid snooze link localhost/house until 2999-01-01 00:00 UTC"]);
+	assert_eq!(lines(&["new"]), ["1 -> [http://localhost/indexes] Notes on indexes"]);
+
+	//Nothing is snoozed if an item can't be found, or if the date makes no sense
+	assert!(!run_cork(&["snooze", "1", "localhost/nowhere", "1w"]).status.success());
+	assert!(!run_cork(&["snooze", "1", "tomorrow"]).status.success());
+	assert!(!run_cork(&["snooze", "1"]).status.success());
+	assert_eq!(lines(&["new"]).len(), 1);
+	assert_eq!(lines(&["snoozed"]).len(), 1);
+}
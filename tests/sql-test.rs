@@ -0,0 +1,45 @@
+use rusqlite::Connection;
+use serde_json::Value;
+
+mod utils;
+use utils::*;
+
+#[test]
+fn sql_test() {
+	ensure_new_database();
+
+	let _feed = Miniserve::launch("./assets/search.rss", None);
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+	assert!(run_cork(&["new"]).status.success());
+
+	//Unread items with their quickmarks, a header line first
+	let unread = lines(&["sql", "SELECT quickmark, title, feed_link FROM v1_unread_items ORDER BY quickmark"]);
+	assert_eq!(unread.len(), 4);
+	assert_eq!(unread[0], "quickmark\ttitle\tfeed_link");
+	assert_eq!(unread[1], "1\tPostgres vacuum explained\thttp://localhost:8080");
+
+	//Reading shows up in the stats and the history
+	assert!(run_cork(&["mark", "1"]).status.success());
+	let stats = lines(&["sql", "SELECT items, unread, read, last_read IS NOT NULL FROM v1_feed_stats"]);
+	assert_eq!(stats[1], "3\t2\t1\t1");
+	let history = lines(&["sql", "SELECT title, read_at FROM v1_history"]);
+	assert_eq!(history.len(), 2);
+	assert!(history[1].starts_with("Postgres vacuum explained\t20"));
+
+	//JSON keeps the column names and types, dates are ISO 8601
+	let output = run_cork(&["sql", "SELECT quickmark, title, published FROM v1_unread_items ORDER BY quickmark", "--format", "json"]);
+	let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+	let rows = json.as_array().unwrap();
+	assert_eq!(rows.len(), 2);
+	assert_eq!(rows[0]["quickmark"], 2);
+	assert!(rows[0]["title"].is_string());
+	assert!(rows[0]["published"].as_str().unwrap().ends_with('Z'));
+
+	//Nothing that writes is allowed, the database is left alone
+	assert!(!run_cork(&["sql", "DELETE FROM items"]).status.success());
+	assert!(!run_cork(&["sql", "CREATE TEMP TABLE t (x)"]).status.success());
+	assert!(!run_cork(&["sql", "SELECT * FROM no_such_view"]).status.success());
+	assert!(!run_cork(&["sql"]).status.success());
+	let db = Connection::open("corkdb").unwrap();
+	assert_eq!(count_items(&db), 3);
+}
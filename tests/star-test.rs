@@ -0,0 +1,56 @@
+use rusqlite::Connection;
+use serde_json::Value;
+
+mod utils;
+use utils::*;
+
+#[test]
+fn star_test() {
+	ensure_new_database();
+
+	let _feed = Miniserve::launch("./assets/search.rss", None);
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+	assert_eq!(lines(&["new"]).len(), 3);
+	assert!(lines(&["starred"]).is_empty());
+
+	//Starred items leave the unread list, by quickmark or by link (with or without the protocol)
+	assert!(run_cork(&["star", "1"]).status.success());
+	let new = lines(&["new"]);
+	assert_eq!(new.len(), 2);
+	assert!(!new.iter().any(|l| l.contains("Postgres vacuum explained")));
+	assert!(run_cork(&["star", "localhost/indexes"]).status.success());
+	assert_eq!(lines(&["new"]), ["1 -> [http://localhost/house] Cleaning the house"]);
+
+	//Listed with their hashes
+	let starred = lines(&["starred"]);
+	assert_eq!(starred.len(), 2);
+	let vacuum_hash = hash_strings(&["Postgres vacuum explained", "http://localhost/vacuum"]);
+	assert!(starred.iter().any(|l| l.starts_with(&vacuum_hash) && l.contains("Postgres vacuum explained")));
+
+	//Nothing is starred if any of the items can't be found
+	assert!(!run_cork(&["star", "1", "localhost/nowhere"]).status.success());
+	assert!(!run_cork(&["star", "99"]).status.success());
+	assert_eq!(lines(&["starred"]).len(), 2);
+
+	//Marking everything leaves them unread
+	assert!(run_cork(&["mark", "--all"]).status.success());
+	let output = run_cork(&["starred", "--format", "json"]);
+	let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+	assert!(json.as_array().unwrap().iter().all(|item| item["read"] == false && item["starred_at"].is_string()));
+
+	//Unstarring puts unread items back in the list
+	assert!(run_cork(&["unstar", &vacuum_hash]).status.success());
+	assert_eq!(lines(&["starred"]).len(), 1);
+	assert!(lines(&["new"])[0].contains("Postgres vacuum explained"));
+
+	//Read starred items are never pruned
+	assert!(run_cork(&["markhash", &vacuum_hash]).status.success());
+	assert!(run_cork(&["star", "localhost/vacuum"]).status.success());
+	assert!(run_cork(&["markhash", &hash_strings(&["Notes on indexes", "http://localhost/indexes"])]).status.success());
+	assert!(run_cork(&["config", "keep_items", "0"]).status.success());
+	assert_eq!(lines(&["prune"]), ["Pruned 1 items"]);
+	let db = Connection::open("corkdb").unwrap();
+	assert_eq!(count_items(&db), 2);
+	assert_eq!(lines(&["starred"]).len(), 2);
+	assert_eq!(lines(&["doctor"]), ["No problems found"]);
+}
@@ -0,0 +1,34 @@
+use std::str::from_utf8;
+
+use rusqlite::Connection;
+
+mod utils;
+use utils::*;
+
+#[test]
+fn terminal_escapes() {
+	ensure_new_database();
+
+	let _feed = Miniserve::launch("./assets/escapes.rss", None);
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+
+	let new_result = run_cork(&["new"]);
+	assert!(new_result.status.success());
+	let new_output = from_utf8(&new_result.stdout).expect("Could not read program output");
+
+	//No escape, control or bidi characters make it to the terminal
+	assert!(!new_output.contains('\u{1b}'));
+	assert!(!new_output.contains('\u{7}'));
+	assert!(!new_output.contains('\u{9b}'));
+	assert!(!new_output.contains('\u{202e}'));
+	assert!(!new_output.contains("pwned"));
+	assert!(new_output.contains("Retitle the window"));
+	assert!(new_output.contains("Hidden text and reversed"));
+
+	//The database keeps what the feed sent
+	let db = Connection::open("corkdb").unwrap();
+	let stored_link: String = db.query_row(
+		"SELECT url FROM items WHERE url LIKE '%escapes/2%';", [], |row| row.get(0)
+	).unwrap();
+	assert!(stored_link.contains('\u{9b}'));
+}
@@ -0,0 +1,60 @@
+use rusqlite::Connection;
+
+mod utils;
+use utils::*;
+
+///Makes every write to the quickmarks table fail, the last step of add, up and mark.
+fn break_quickmarks(db: &Connection) {
+	db.execute_batch(
+		"CREATE TRIGGER no_new_quickmarks BEFORE INSERT ON quickmarks
+		BEGIN SELECT RAISE(ABORT, 'quickmarks are broken'); END;
+		CREATE TRIGGER no_old_quickmarks BEFORE DELETE ON quickmarks
+		BEGIN SELECT RAISE(ABORT, 'quickmarks are broken'); END;"
+	).unwrap();
+}
+
+fn fix_quickmarks(db: &Connection) {
+	db.execute_batch(
+		"DROP TRIGGER no_new_quickmarks;
+		DROP TRIGGER no_old_quickmarks;"
+	).unwrap();
+}
+
+fn count_read(db: &Connection) -> i64 {
+	db.query_row("SELECT COUNT(*) FROM items WHERE read=1;", [], |row| row.get(0)).unwrap()
+}
+
+#[test]
+fn transactions() {
+	ensure_new_database();
+	assert!(run_cork(&["feeds"]).status.success());
+	let db = Connection::open("corkdb").unwrap();
+
+	//add fails at the end, the channel and items are not left behind
+	let mut feed = Miniserve::launch("./assets/sample2.rss", None);
+	break_quickmarks(&db);
+	assert!(!run_cork(&["add", "http://localhost:8080"]).status.success());
+	assert_eq!(count_channels(&db), 0);
+	assert_eq!(count_items(&db), 0);
+
+	fix_quickmarks(&db);
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+	assert_eq!(count_items(&db), 3);
+	feed.kill();
+
+	//same for up
+	let _newer_feed = Miniserve::launch("./assets/sample2-next-week.rss", None);
+	break_quickmarks(&db);
+	assert!(!run_cork(&["up"]).status.success());
+	assert_eq!(count_items(&db), 3);
+
+	//and mark, the item stays unread
+	assert!(!run_cork(&["markhash", &hash_string("First post")]).status.success());
+	assert_eq!(count_read(&db), 0);
+
+	fix_quickmarks(&db);
+	assert!(run_cork(&["markhash", &hash_string("First post")]).status.success());
+	assert_eq!(count_read(&db), 1);
+	assert!(run_cork(&["up"]).status.success());
+	assert_eq!(count_items(&db), 4);
+}
@@ -0,0 +1,39 @@
+mod utils;
+use utils::*;
+
+#[test]
+fn undo_after_up() {
+	ensure_new_database();
+
+	let mut feed = Miniserve::launch("./assets/search.rss", None);
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+	assert!(run_cork(&["mark", "--all"]).status.success());
+
+	//An update (say from a timer) in between doesn't get in the way of undoing
+	feed.kill();
+	feed = Miniserve::launch("./assets/search-more.rss", None);
+	assert!(run_cork(&["up"]).status.success());
+	assert_eq!(lines(&["new"]).len(), 1);
+	assert!(lines(&["undo", "--list"])[0].contains("mark --all"));
+	assert_eq!(lines(&["undo"]), ["Undid mark --all"]);
+	let new = lines(&["new"]);
+	assert_eq!(new.len(), 4);
+	assert!(new[3].contains("Reading query plans"));
+
+	//Items added by an update don't take the place of pruned ones that undo brings back
+	assert!(run_cork(&["mark", "4"]).status.success());
+	assert!(run_cork(&["config", "keep_items", "0"]).status.success());
+	assert!(run_cork(&["prune"]).status.success());
+	assert!(lines(&["history"]).is_empty());
+	feed.kill();
+	let _feed = Miniserve::launch("./assets/search-most.rss", None);
+	assert!(run_cork(&["up"]).status.success());
+	assert_eq!(lines(&["undo", "2"]), ["Undid prune", "Undid config keep_items 0"]);
+	let history = lines(&["history"]);
+	assert_eq!(history.len(), 1);
+	assert!(history[0].contains("Reading query plans"));
+	let new = lines(&["new"]);
+	assert_eq!(new.len(), 4);
+	assert!(new[3].contains("Partitioning large tables"));
+	assert_eq!(lines(&["search", "partitioning"]).len(), 1);
+}
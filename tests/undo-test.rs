@@ -0,0 +1,66 @@
+mod utils;
+use utils::*;
+
+#[test]
+fn undo_test() {
+	ensure_new_database();
+
+	let _feed = Miniserve::launch("./assets/search.rss", None);
+	assert!(!run_cork(&["undo"]).status.success());
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+	assert_eq!(lines(&["new"]).len(), 3);
+
+	//A single mark
+	assert!(run_cork(&["mark", "1"]).status.success());
+	assert_eq!(lines(&["new"]).len(), 2);
+	assert_eq!(lines(&["undo"]), ["Undid mark 1"]);
+	assert_eq!(lines(&["new"]).len(), 3);
+	assert!(lines(&["history"]).is_empty());
+
+	//Commands that changed nothing are not recorded
+	assert!(run_cork(&["up"]).status.success());
+	let journal = lines(&["undo", "--list"]);
+	assert_eq!(journal.len(), 1);
+	assert!(journal[0].starts_with("1 ") && journal[0].contains("add http://localhost:8080"));
+
+	//Marking everything then removing the feed, undone in one go
+	assert!(run_cork(&["mark", "--all"]).status.success());
+	assert!(run_cork(&["remove", "localhost:8080"]).status.success());
+	let journal = lines(&["undo", "--list"]);
+	assert_eq!(journal.len(), 3);
+	assert!(journal[0].contains("remove localhost:8080"));
+	assert!(journal[1].contains("mark --all"));
+
+	//Nothing is undone when asking for more than there is
+	assert!(!run_cork(&["undo", "4"]).status.success());
+	assert!(lines(&["feeds"]).iter().all(|l| !l.contains("localhost")));
+
+	assert_eq!(lines(&["undo", "2"]), ["Undid remove localhost:8080", "Undid mark --all"]);
+	assert_eq!(lines(&["feeds"]), ["http://localhost:8080"]);
+	assert_eq!(lines(&["new"]).len(), 3);
+	//the search index has the items again
+	assert_eq!(lines(&["search", "vacuum"]).len(), 2);
+
+	//Pruned items come back, and settings go back to what they were
+	assert!(run_cork(&["mark", "--all"]).status.success());
+	assert!(run_cork(&["config", "keep_items", "0"]).status.success());
+	assert!(run_cork(&["prune"]).status.success());
+	assert!(lines(&["history"]).is_empty());
+	assert_eq!(lines(&["undo", "2"]), ["Undid prune", "Undid config keep_items 0"]);
+	assert_eq!(lines(&["history"]).len(), 3);
+	assert!(lines(&["config"]).contains(&String::from("keep_items is not set")));
+
+	//Undoing the subscription removes the feed
+	assert!(run_cork(&["undo", "2"]).status.success());
+	assert!(lines(&["feeds"]).iter().all(|l| !l.contains("localhost")));
+	assert!(lines(&["undo", "--list"]).is_empty());
+
+	//Without retention nothing is kept to undo
+	assert!(run_cork(&["config", "keep_journal_days", "0"]).status.success());
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+	assert!(lines(&["undo", "--list"]).is_empty());
+	assert!(!run_cork(&["undo"]).status.success());
+	assert_eq!(lines(&["new"]).len(), 3);
+
+	assert!(!run_cork(&["undo", "0"]).status.success());
+}
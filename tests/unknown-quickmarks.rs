@@ -0,0 +1,35 @@
+use std::str::from_utf8;
+
+use rusqlite::Connection;
+
+mod utils;
+use utils::*;
+
+#[test]
+fn unknown_quickmarks() {
+	ensure_new_database();
+
+	let _feed = Miniserve::launch("./assets/search.rss", None);
+	assert!(run_cork(&["add", "localhost:8080"]).status.success());
+	assert!(run_cork(&["new"]).status.success());
+
+	//A position without an item fails, and so does the whole command
+	assert!(!run_cork(&["mark", "99"]).status.success());
+	let result = run_cork(&["mark", "1", "99"]);
+	assert!(!result.status.success());
+	assert!(from_utf8(&result.stderr).unwrap().contains("99"));
+
+	let db = Connection::open("corkdb").unwrap();
+	let read: i64 = db.query_row("SELECT COUNT(*) FROM items WHERE read = 1;", [], |row| row.get(0)).unwrap();
+	assert_eq!(read, 0);
+
+	//The table itself refuses a second item at a position, or a second position for an item
+	assert!(db.execute("INSERT INTO quickmarks (position, hash) VALUES (1, 'other');", []).is_err());
+	assert!(db.execute(
+		"INSERT INTO quickmarks (position, hash) SELECT 99, hash FROM quickmarks WHERE position = 1;", []
+	).is_err());
+
+	assert!(run_cork(&["mark", "1", "2"]).status.success());
+	let read: i64 = db.query_row("SELECT COUNT(*) FROM items WHERE read = 1;", [], |row| row.get(0)).unwrap();
+	assert_eq!(read, 2);
+}
@@ -0,0 +1,44 @@
+use std::str::from_utf8;
+
+mod utils;
+use utils::*;
+
+#[test]
+fn unmark_test() {
+	ensure_new_database();
+
+	let _feed = Miniserve::launch("./assets/search.rss", None);
+	assert!(run_cork(&["add", "http://localhost:8080"]).status.success());
+	assert_eq!(lines(&["new"]).len(), 3);
+
+	//By position in the history, the item goes back in the queue
+	assert!(run_cork(&["mark", "1"]).status.success());
+	assert_eq!(lines(&["new"]).len(), 2);
+	let history = lines(&["history"]);
+	assert_eq!(history.len(), 1);
+	assert!(history[0].contains("Postgres vacuum explained"));
+	assert!(run_cork(&["unmark", "1"]).status.success());
+	assert!(lines(&["history"]).is_empty());
+	let new = lines(&["new"]);
+	assert_eq!(new.len(), 3);
+	assert!(new.iter().any(|l| l.contains("Postgres vacuum explained")));
+
+	//By hash and by link (with or without the protocol)
+	assert!(run_cork(&["mark", "--all"]).status.success());
+	let vacuum_hash = hash_strings(&["Postgres vacuum explained", "http://localhost/vacuum"]);
+	assert!(run_cork(&["unmark", &vacuum_hash, "localhost/house"]).status.success());
+	let new = lines(&["new"]);
+	assert_eq!(new.len(), 2);
+	assert!(new.iter().any(|l| l.contains("Postgres vacuum explained")));
+	assert!(new.iter().any(|l| l.contains("Cleaning the house")));
+	assert_eq!(lines(&["history"]).len(), 1);
+
+	//Nothing is unmarked if any of the items can't be found
+	assert!(!run_cork(&["unmark", "1", "2"]).status.success());
+	assert!(!run_cork(&["unmark", "1", "localhost/nowhere"]).status.success());
+	let zero = run_cork(&["unmark", "0"]);
+	assert!(!zero.status.success());
+	assert!(from_utf8(&zero.stderr).unwrap().contains("position 0"));
+	assert_eq!(lines(&["history"]).len(), 1);
+	assert_eq!(lines(&["new"]).len(), 2);
+}
@@ -2,17 +2,25 @@ use std::{
 	env,
 	fs,
 	path::Path,
-	process::{Child, Command, Stdio, Output}
+	process::{Child, Command, Stdio, Output},
+	str::from_utf8
 };
 
 use rusqlite::Connection;
 use sha2::{Sha256, Digest};
 
-///Deletes the database file if it's present
+///Deletes the database file if it's present, along with its WAL files
+///(a stale WAL would be replayed into the new database), its locks and its snapshots.
 pub fn ensure_new_database() {
-	let db_path = Path::new("./corkdb");
-	if db_path.exists() {
-		fs::remove_file(db_path).unwrap();
+	for file in ["./corkdb", "./corkdb-wal", "./corkdb-shm", "./corkdb.lock", "./corkdb.in-use"] {
+		let db_path = Path::new(file);
+		if db_path.exists() {
+			fs::remove_file(db_path).unwrap();
+		}
+	}
+	let snapshots = Path::new("./corkdb.snapshots");
+	if snapshots.exists() {
+		fs::remove_dir_all(snapshots).unwrap();
 	}
 }
 
@@ -44,10 +52,20 @@ impl Drop for Miniserve {
 	}
 }
 
+///`cargo run`, built with the same features as the tests
+fn cargo_run() -> Command {
+	let mut command = Command::new("cargo");
+	command.args(["run", "--quiet"]);
+	if cfg!(feature = "encryption") {
+		command.args(["--features", "encryption"]);
+	}
+	command.arg("--");
+	command
+}
+
 ///Run the application
 pub fn run_cork(parameters: &[&str]) -> Output {
-	Command::new("cargo")
-		.args(&["run", "--quiet", "--"])
+	cargo_run()
 		.args(parameters)
 		//Setting $CORKDB_TEST to True, so that corkboard will use "./corkdb" as the database path
 		.env("CORKDB_TEST", "true")
@@ -55,6 +73,26 @@ pub fn run_cork(parameters: &[&str]) -> Output {
 		.expect("Cargo run failed")
 }
 
+///Run the application with some extra environment variables set
+pub fn run_cork_with_env(parameters: &[&str], variables: &[(&str, &str)]) -> Output {
+	cargo_run()
+		.args(parameters)
+		.env("CORKDB_TEST", "true")
+		.envs(variables.iter().copied())
+		.output()
+		.expect("Cargo run failed")
+}
+
+///Lines printed by a command, fails the test if the command itself failed
+pub fn lines(parameters: &[&str]) -> Vec<String> {
+	let output = run_cork(parameters);
+	assert!(output.status.success(), "{parameters:?} failed");
+	from_utf8(&output.stdout).expect("Could not read program output")
+		.lines()
+		.map(String::from)
+		.collect()
+}
+
 ///Count the channels in the database
 pub fn count_channels(db: &Connection) -> i64 {
 	let db_result:Vec<_> = db.prepare("SELECT COUNT(*) FROM channels;").unwrap()
@@ -0,0 +1,33 @@
+use std::str::from_utf8;
+
+mod utils;
+use utils::*;
+
+#[test]
+fn validate_test() {
+	ensure_new_database();
+
+	//A well formed feed passes
+	let good = run_cork(&["validate", "./assets/sample1.rss"]);
+	assert!(good.status.success());
+	let good_output = from_utf8(&good.stdout).expect("Could not read output as string");
+	assert!(good_output.contains("RSS"));
+
+	//A feed that parses but has problems still passes, the problems are listed with their position
+	let sloppy = run_cork(&["validate", "./assets/sloppy.rss"]);
+	assert!(sloppy.status.success());
+	let sloppy_output = from_utf8(&sloppy.stdout).expect("Could not read output as string");
+	assert!(sloppy_output.contains("yesterday around noon"));
+	assert!(sloppy_output.contains("item skipped"));
+	assert!(sloppy_output.contains("description"));
+	assert!(sloppy_output.contains("1 skipped"));
+
+	//Broken XML is fatal, and the parser error points at where it broke
+	let broken = run_cork(&["validate", "./assets/broken.rss"]);
+	assert!(!broken.status.success());
+	let broken_errors = from_utf8(&broken.stderr).expect("Could not read output as string");
+	assert!(broken_errors.contains("8:"));
+
+	//Validating never creates the database
+	assert!(!std::path::Path::new("./corkdb").exists());
+}